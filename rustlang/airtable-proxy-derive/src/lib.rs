@@ -0,0 +1,968 @@
+//! `#[derive(AirtableTable)]`, describes a table with an ordinary struct,
+//! instead of the `gen_airtable_schema!` DSL.
+//!
+//! The struct is the table's `Mapped` type, and the derive generates a module
+//! (the struct's name in snake case, or `module = "..."`) with everything
+//! else the DSL would, the `Fields`, the `Table` impl, the `create_*` fns,
+//! `param`, and `endpoints`. `gen_derived_schema!` then makes the `gen` module
+//! from those modules.
+//!
+//! ```ignore
+//! #[derive(Debug, Serialize, AirtableTable)]
+//! #[airtable(table = "Invoice")]
+//! #[airtable(endpoint = query_by_invoice_id, arg = String, exec = [param::as_id_query, one, Invoice::create_one])]
+//! pub struct Invoice {
+//!     /// The invoice number, like `2020-001`.
+//!     #[airtable(source = "Invoice Number")]
+//!     pub number: String,
+//!     #[airtable(source = "Total Amount", from = Currency, exec = format_currency)]
+//!     pub total: String,
+//!     #[airtable(source = "Client", from = IDs, exec = InvoiceClient::fetch_and_create_first)]
+//!     pub client: InvoiceClient,
+//! }
+//!
+//! gen_derived_schema!(invoice, invoice_client);
+//! ```
+//!
+//! On the struct:
+//!
+//! - `table = "..."` the name of the table in airtable, this is required,
+//! - `module = "..."`, `base = "..."`, and `key = "..."`, like the DSL's,
+//! - and `endpoint = name, arg = Type, exec = [...]`, with an optional
+//!   `returns = Type` (the struct by default), in their own attribute
+//!   for each endpoint, which can also have a `method = POST`, a
+//!   `path = ["year", u32]` (the `arg` by default), a `query = Type`, a
+//!   `body = Type`, and the `keys = source` of its response, like the DSL's.
+//!
+//! On each field, `source = "..."` is the airtable column name, `from = Type`
+//! is what's deserialized from it (the field's type by default), and
+//! `exec = f` or `exec = [f, g]` transforms it into the field's type.
+//! Airtable leaves out empty cells, `default = ...` is the value (before
+//! `exec`) when it does, and `required` makes that an error even for an
+//! `Option`, which is otherwise `None`. A linked record field with `link`
+//! is a `Link`, its IDs, unless the request's `?expand=` names it. A field
+//! with `filter` can be filtered on in the list, `GET /{module}?field=value`,
+//! and the first with `unique` is where the batch, `POST /{module}/batch`,
+//! looks up the keys that aren't record IDs.
+//!
+//! A field with `computed = fields` or `computed = mapped` isn't read from a
+//! column, its `exec` makes it from the record's `&Fields`, or from the
+//! `&Partial` (the other, non computed, fields after their `exec`).
+//!
+//! A field with `reverse = Type` is an `Option<Vec<Type>>` of the records of
+//! that table that link to this one, by their `source = "..."` link column,
+//! or this table's `inverse = "..."` of it, which are only fetched when the
+//! field is expanded.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, Ident, LitStr, Token, Type};
+
+#[proc_macro_derive(AirtableTable, attributes(airtable))]
+pub fn derive_airtable_table(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+const KEYS: &[&str] = &[
+    "table", "module", "base", "key", "endpoint", "arg", "returns", "source", "from", "exec",
+    "default", "required", "computed", "link", "filter", "unique", "reverse", "inverse", "method",
+    "path", "query", "body", "keys",
+];
+const TABLE_KEYS: &[&str] = &["table", "module", "base", "key"];
+const ENDPOINT_KEYS: &[&str] = &[
+    "endpoint", "arg", "returns", "exec", "method", "path", "query", "body", "keys",
+];
+const FIELD_KEYS: &[&str] = &[
+    "source", "from", "exec", "default", "required", "computed", "link", "filter", "unique",
+    "reverse", "inverse",
+];
+
+/// One `key = value` in an `#[airtable(...)]` attribute.
+struct Item {
+    key: Ident,
+    value: Value,
+}
+
+enum Value {
+    Str(LitStr),
+    Type(Box<Type>),
+    Ident(Ident),
+    Exec(Vec<Expr>),
+    Expr(Box<Expr>),
+    Flag,
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+        let name = key.to_string();
+        if !KEYS.contains(&name.as_str()) {
+            return Err(syn::Error::new(
+                key.span(),
+                format!(
+                    "unknown airtable attribute `{}`, expected one of: {}",
+                    name,
+                    KEYS.join(", ")
+                ),
+            ));
+        }
+        if ["required", "link", "filter", "unique"].contains(&name.as_str()) {
+            return Ok(Self {
+                key,
+                value: Value::Flag,
+            });
+        }
+        input.parse::<Token![=]>()?;
+        let value = match name.as_str() {
+            "from" | "arg" | "returns" | "query" | "body" | "reverse" => {
+                Value::Type(Box::new(input.parse()?))
+            }
+            "endpoint" | "computed" | "method" | "keys" => Value::Ident(input.parse()?),
+            // a chain of fns is `[f, g]`, and one fn doesn't need the brackets,
+            // and so is a path
+            "exec" | "path" => Value::Exec(match input.parse()? {
+                Expr::Array(array) => array.elems.into_iter().collect(),
+                exec => vec![exec],
+            }),
+            "default" => Value::Expr(Box::new(input.parse()?)),
+            _ => Value::Str(input.parse()?),
+        };
+        Ok(Self { key, value })
+    }
+}
+
+/// The items of each `#[airtable(...)]` attribute in `attrs`.
+fn parse_attrs(attrs: &[Attribute]) -> syn::Result<Vec<Vec<Item>>> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("airtable"))
+        .map(|attr| {
+            attr.parse_args_with(Punctuated::<Item, Token![,]>::parse_terminated)
+                .map(|items| items.into_iter().collect())
+        })
+        .collect()
+}
+
+/// Everything that can be set with `#[airtable(...)]`, where it's allowed.
+#[derive(Default)]
+struct Attrs {
+    table: Option<LitStr>,
+    module: Option<LitStr>,
+    base: Option<LitStr>,
+    key: Option<LitStr>,
+    endpoint: Option<Ident>,
+    arg: Option<Type>,
+    returns: Option<Type>,
+    source: Option<LitStr>,
+    from: Option<Type>,
+    exec: Option<Vec<Expr>>,
+    default: Option<Expr>,
+    required: Option<Ident>,
+    computed: Option<Ident>,
+    link: Option<Ident>,
+    filter: Option<Ident>,
+    unique: Option<Ident>,
+    reverse: Option<Type>,
+    inverse: Option<LitStr>,
+    method: Option<Ident>,
+    path: Option<Vec<Expr>>,
+    query: Option<Type>,
+    body: Option<Type>,
+    keys: Option<Ident>,
+}
+
+fn set<T>(slot: &mut Option<T>, key: &Ident, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(key.span(), format!("duplicate `{}`", key)));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+impl Attrs {
+    /// Adds the `items`, which can only be the `allowed` keys on the `place` they are.
+    fn add(&mut self, items: Vec<Item>, allowed: &[&str], place: &str) -> syn::Result<()> {
+        for Item { key, value } in items {
+            let name = key.to_string();
+            if !allowed.contains(&name.as_str()) {
+                return Err(syn::Error::new(
+                    key.span(),
+                    format!(
+                        "`{}` can't be used {}, expected one of: {}",
+                        name,
+                        place,
+                        allowed.join(", ")
+                    ),
+                ));
+            }
+            match (name.as_str(), value) {
+                ("table", Value::Str(value)) => set(&mut self.table, &key, value)?,
+                ("module", Value::Str(value)) => set(&mut self.module, &key, value)?,
+                ("base", Value::Str(value)) => set(&mut self.base, &key, value)?,
+                ("key", Value::Str(value)) => set(&mut self.key, &key, value)?,
+                ("source", Value::Str(value)) => set(&mut self.source, &key, value)?,
+                ("endpoint", Value::Ident(value)) => set(&mut self.endpoint, &key, value)?,
+                ("arg", Value::Type(value)) => set(&mut self.arg, &key, *value)?,
+                ("returns", Value::Type(value)) => set(&mut self.returns, &key, *value)?,
+                ("from", Value::Type(value)) => set(&mut self.from, &key, *value)?,
+                ("exec", Value::Exec(value)) => set(&mut self.exec, &key, value)?,
+                ("default", Value::Expr(value)) => set(&mut self.default, &key, *value)?,
+                ("required", Value::Flag) => set(&mut self.required, &key, key.clone())?,
+                ("computed", Value::Ident(value)) => set(&mut self.computed, &key, value)?,
+                ("link", Value::Flag) => set(&mut self.link, &key, key.clone())?,
+                ("filter", Value::Flag) => set(&mut self.filter, &key, key.clone())?,
+                ("unique", Value::Flag) => set(&mut self.unique, &key, key.clone())?,
+                ("reverse", Value::Type(value)) => set(&mut self.reverse, &key, *value)?,
+                ("inverse", Value::Str(value)) => set(&mut self.inverse, &key, value)?,
+                ("method", Value::Ident(value)) => set(&mut self.method, &key, value)?,
+                ("path", Value::Exec(value)) => set(&mut self.path, &key, value)?,
+                ("query", Value::Type(value)) => set(&mut self.query, &key, *value)?,
+                ("body", Value::Type(value)) => set(&mut self.body, &key, *value)?,
+                ("keys", Value::Ident(value)) => set(&mut self.keys, &key, value)?,
+                _ => unreachable!("the value is parsed by its key"),
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Endpoint {
+    name: Ident,
+    arg: Type,
+    returns: Option<Type>,
+    exec: Vec<Expr>,
+    method: Option<Ident>,
+    /// The segments of the path, literals or types, the `arg` without it.
+    path: Option<Vec<TokenStream2>>,
+    query: Option<Type>,
+    body: Option<Type>,
+    keys: Option<Ident>,
+}
+
+const METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE"];
+const KEY_CASES: &[&str] = &["fields", "source", "camelCase", "snake_case"];
+
+/// The segments of an endpoint's `path = ["year", u32]`, each a string, or a type.
+fn path_segments(path: Vec<Expr>) -> syn::Result<Vec<TokenStream2>> {
+    path.into_iter()
+        .map(|segment| match &segment {
+            Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(literal),
+                ..
+            }) => Ok(quote!(#literal)),
+            Expr::Path(path) if path.path.get_ident().is_some() => Ok(quote!(#path)),
+            _ => Err(syn::Error::new(
+                segment.span(),
+                "a segment of the path is a \"literal\", or a type that's parsed from it, like u32",
+            )),
+        })
+        .collect()
+}
+
+struct Field {
+    name: Ident,
+    ty: Type,
+    source: LitStr,
+    from: Option<Type>,
+    exec: Vec<Expr>,
+    default: Option<Expr>,
+    required: bool,
+    link: bool,
+    filter: bool,
+    unique: bool,
+}
+
+/// A field that's computed from the whole record, `from` either its `fields`,
+/// or its `mapped` fields (the `Partial`).
+struct Computed {
+    name: Ident,
+    ty: Type,
+    from: Ident,
+    exec: Vec<Expr>,
+}
+
+/// The records of the table `ty` that link to this one, found `by` their
+/// link column, or this table's inverse of it.
+struct Reverse {
+    name: Ident,
+    ty: Type,
+    by: TokenStream2,
+}
+
+/// `InvoiceClient` to `invoice_client`, and a run of capitals is one word,
+/// so `HTTPClient` is `http_client`.
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+    for (idx, c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let after_word = idx > 0 && !chars[idx - 1].is_uppercase() && chars[idx - 1] != '_';
+            let ends_run = idx > 0
+                && chars[idx - 1].is_uppercase()
+                && chars.get(idx + 1).is_some_and(|next| next.is_lowercase());
+            if after_word || ends_run {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(*c);
+        }
+    }
+    snake
+}
+
+/// The `tokens` as they're written, like `Vec<String>`, which `stringify!`
+/// spaces out to `Vec < String >` when they come from a proc macro.
+fn display(tokens: TokenStream2) -> String {
+    let spaced: Vec<char> = tokens.to_string().chars().collect();
+    let is_word = |c: Option<&char>| c.is_some_and(|c| c.is_alphanumeric() || "_'\"".contains(*c));
+    spaced
+        .iter()
+        .enumerate()
+        .filter(|(idx, c)| {
+            let (before, after) = (
+                idx.checked_sub(1).map(|idx| &spaced[idx]),
+                spaced.get(idx + 1),
+            );
+            // a space is kept after a `,`, and before a word, after a word or a `>`
+            let between = (is_word(before) || before == Some(&'>')) && is_word(after);
+            **c != ' ' || before == Some(&',') || between
+        })
+        .map(|(_, c)| c)
+        .collect()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "an AirtableTable can't have generics",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            syn::Fields::Named(fields) => &fields.named,
+            fields => {
+                return Err(syn::Error::new(
+                    fields.span(),
+                    "an AirtableTable needs named fields, one for each column",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "an AirtableTable has to be a struct",
+            ))
+        }
+    };
+
+    // the struct's attributes, the table's, and one for each endpoint
+    let mut table = Attrs::default();
+    let mut endpoints = vec![];
+    for items in parse_attrs(&input.attrs)? {
+        let is_endpoint = items.iter().any(|item| item.key == "endpoint");
+        if !is_endpoint {
+            table.add(items, TABLE_KEYS, "on the struct, without an `endpoint`")?;
+            continue;
+        }
+        let mut endpoint = Attrs::default();
+        endpoint.add(items, ENDPOINT_KEYS, "with an `endpoint`")?;
+        let name = endpoint.endpoint.expect("it's an endpoint");
+        let arg = endpoint.arg.ok_or_else(|| {
+            syn::Error::new(name.span(), "missing the `arg = Type` of this endpoint")
+        })?;
+        if let Some(method) = &endpoint.method {
+            if !METHODS.contains(&method.to_string().as_str()) {
+                return Err(syn::Error::new(
+                    method.span(),
+                    format!("the method is one of: {}", METHODS.join(", ")),
+                ));
+            }
+        }
+        if let Some(keys) = &endpoint.keys {
+            if !KEY_CASES.contains(&keys.to_string().as_str()) {
+                return Err(syn::Error::new(
+                    keys.span(),
+                    format!("the keys are one of: {}", KEY_CASES.join(", ")),
+                ));
+            }
+        }
+        endpoints.push(Endpoint {
+            name,
+            arg,
+            returns: endpoint.returns,
+            exec: endpoint.exec.unwrap_or_default(),
+            method: endpoint.method,
+            path: endpoint.path.map(path_segments).transpose()?,
+            query: endpoint.query,
+            body: endpoint.body,
+            keys: endpoint.keys,
+        });
+    }
+    if table.table.is_none() {
+        return Err(syn::Error::new(
+            ident.span(),
+            "missing `#[airtable(table = \"...\")]`, the name of the table in airtable",
+        ));
+    }
+    let module = match &table.module {
+        Some(module) => module.parse::<Ident>()?,
+        None => Ident::new(&snake_case(&ident.to_string()), ident.span()),
+    };
+
+    // a field is either read from a column, computed from the others, or
+    // the records of another table that link to this one
+    let mut sourced = vec![];
+    let mut computed = vec![];
+    let mut reverse = vec![];
+    for field in fields {
+        let name = field.ident.clone().expect("the fields are named");
+        let mut attrs = Attrs::default();
+        for items in parse_attrs(&field.attrs)? {
+            attrs.add(items, FIELD_KEYS, "on a field")?;
+        }
+        if let Some(ty) = attrs.reverse.take() {
+            reverse.push(reverse_field(name, ty, attrs)?);
+            continue;
+        }
+        match attrs.computed.take() {
+            Some(from) => computed.push(computed_field(name, field.ty.clone(), from, attrs)?),
+            None => sourced.push(source_field(name, field.ty.clone(), attrs)?),
+        }
+    }
+
+    Ok(generate(
+        input, &module, &table, &sourced, &computed, &reverse, &endpoints,
+    ))
+}
+
+fn reverse_field(name: Ident, ty: Type, attrs: Attrs) -> syn::Result<Reverse> {
+    let not_reverse = [
+        (attrs.from.is_some(), "from"),
+        (attrs.exec.is_some(), "exec"),
+        (attrs.default.is_some(), "default"),
+        (attrs.required.is_some(), "required"),
+        (attrs.computed.is_some(), "computed"),
+        (attrs.link.is_some(), "link"),
+        (attrs.filter.is_some(), "filter"),
+        (attrs.unique.is_some(), "unique"),
+    ];
+    if let Some((_, key)) = not_reverse.iter().find(|(is_set, _)| *is_set) {
+        return Err(syn::Error::new(
+            name.span(),
+            format!("`{}` can't be used on a reverse field", key),
+        ));
+    }
+    let by = match (attrs.source, attrs.inverse) {
+        (Some(source), None) => quote!(::airtable_proxy::reverse::Reverse::Link(#source)),
+        (None, Some(inverse)) => quote!(::airtable_proxy::reverse::Reverse::Inverse(#inverse)),
+        _ => {
+            return Err(syn::Error::new(
+                name.span(),
+                "a reverse field has either a `source`, the link column of the other \
+                 table, or an `inverse`, this table's column of their IDs",
+            ))
+        }
+    };
+    Ok(Reverse { name, ty, by })
+}
+
+fn computed_field(name: Ident, ty: Type, from: Ident, attrs: Attrs) -> syn::Result<Computed> {
+    if from != "fields" && from != "mapped" {
+        return Err(syn::Error::new(
+            from.span(),
+            "a field is `computed = fields` or `computed = mapped`",
+        ));
+    }
+    let not_computed = [
+        (attrs.source.is_some(), "source"),
+        (attrs.from.is_some(), "from"),
+        (attrs.default.is_some(), "default"),
+        (attrs.required.is_some(), "required"),
+        (attrs.link.is_some(), "link"),
+        (attrs.filter.is_some(), "filter"),
+        (attrs.unique.is_some(), "unique"),
+    ];
+    if let Some((_, key)) = not_computed.iter().find(|(is_set, _)| *is_set) {
+        return Err(syn::Error::new(
+            from.span(),
+            format!("a computed field can't have a `{}`, only an `exec`", key),
+        ));
+    }
+    let exec = match attrs.exec {
+        Some(exec) if !exec.is_empty() => exec,
+        _ => {
+            return Err(syn::Error::new(
+                name.span(),
+                "missing the `exec = ...` that computes this field",
+            ))
+        }
+    };
+    Ok(Computed {
+        name,
+        ty,
+        from,
+        exec,
+    })
+}
+
+fn source_field(name: Ident, ty: Type, attrs: Attrs) -> syn::Result<Field> {
+    let source = attrs.source.ok_or_else(|| {
+        syn::Error::new(
+            name.span(),
+            format!(
+                "missing `#[airtable(source = \"...\")]`, the column `{}` is read from",
+                name
+            ),
+        )
+    })?;
+    if let (Some(_), Some(required)) = (&attrs.default, &attrs.required) {
+        return Err(syn::Error::new(
+            required.span(),
+            "a field is either `required` or has a `default`, not both",
+        ));
+    }
+    Ok(Field {
+        name,
+        ty,
+        source,
+        from: attrs.from,
+        exec: attrs.exec.unwrap_or_default(),
+        default: attrs.default,
+        required: attrs.required.is_some(),
+        link: attrs.link.is_some(),
+        filter: attrs.filter.is_some(),
+        unique: attrs.unique.is_some(),
+    })
+}
+
+fn generate(
+    input: &DeriveInput,
+    module: &Ident,
+    attrs: &Attrs,
+    fields: &[Field],
+    computed: &[Computed],
+    reverse: &[Reverse],
+    endpoints: &[Endpoint],
+) -> TokenStream2 {
+    let vis = &input.vis;
+    let ident = &input.ident;
+    let table = attrs
+        .table
+        .as_ref()
+        .expect("`expand` checks the table is named");
+    let module_name = LitStr::new(&module.to_string(), module.span());
+    let base = attrs.base.iter();
+    let key = attrs.key.iter();
+
+    let names: Vec<&Ident> = fields.iter().map(|field| &field.name).collect();
+    let sources = fields.iter().map(|field| &field.source);
+    let types = fields
+        .iter()
+        .map(|field| field.from.as_ref().unwrap_or(&field.ty));
+
+    // each field is transformed by its exec chain, which is spanned so that
+    // a fn with the wrong types points at its attribute
+    let creates = fields.iter().map(|field| {
+        let name = &field.name;
+        let source = &field.source;
+        let missing = match (&field.default, field.required) {
+            (Some(default), _) => quote_spanned!(default.span()=> #default),
+            (None, true) => quote!(return Err(missing())),
+            // without either, only an `Option` (or anything else that can be
+            // deserialized from a `null`) can be missing
+            (None, false) => quote! {
+                match ::airtable_proxy::serde_json::from_value(::airtable_proxy::serde_json::Value::Null) {
+                    Ok(value) => value,
+                    Err(_) => return Err(missing()),
+                }
+            },
+        };
+        let execs = field.exec.iter().map(|exec| {
+            quote_spanned! {exec.span()=>
+                let value = match #exec(ctx, value).await {
+                    Ok(value) => value,
+                    Err(e) => return Err(Error::Create {
+                        table: #table,
+                        id: id.clone(),
+                        field: stringify!(#name),
+                        source: Box::new(e),
+                    }),
+                };
+            }
+        });
+        // a `link` is the IDs, unless it's expanded (or its fields are asked
+        // for), then it's hydrated by the execs, in the scope under it
+        let transform = if field.link {
+            quote! {
+                let value = match ctx.scope().link(stringify!(#name)) {
+                    None => Link::Ids(value),
+                    Some(scope) => {
+                        let parent = ctx.set_scope(scope);
+                        let result = async {
+                            #(#execs)*
+                            Ok::<_, Error>(value)
+                        }
+                        .await;
+                        ctx.set_scope(parent);
+                        Link::Expanded(result?)
+                    }
+                };
+            }
+        } else {
+            quote!(#(#execs)*)
+        };
+        quote! {
+            #name: {
+                let missing = || Error::Create {
+                    table: #table,
+                    id: id.clone(),
+                    field: stringify!(#name),
+                    source: Box::new(Error::MissingValue { column: #source }),
+                };
+                let value = match one.fields.#name {
+                    Some(value) => value,
+                    None => #missing,
+                };
+                #transform
+                value
+            }
+        }
+    });
+
+    // computed fields are made from `&Fields` before the others, or from
+    // `&Partial` after them
+    let computes = |phase: &str| {
+        computed
+            .iter()
+            .filter(|computed| computed.from == phase)
+            .map(|computed| {
+                let name = &computed.name;
+                let ty = &computed.ty;
+                let exec = &computed.exec;
+                let arg = match phase {
+                    "fields" => quote!(&one.fields),
+                    _ => quote!(&partial),
+                };
+                quote! {
+                    let #name: #ty = match compose!(ctx, #arg, [ #(#exec),* ]) {
+                        Ok(value) => value,
+                        Err(e) => return Err(Error::Create {
+                            table: #table,
+                            id: id.clone(),
+                            field: stringify!(#name),
+                            source: Box::new(e),
+                        }),
+                    };
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let from_fields = computes("fields");
+    let from_mapped = computes("mapped");
+
+    // the reverse fields are fetched when they're expanded, in the scope
+    // under them, and created by a boxed fn, since they can link back here
+    let reverse_names: Vec<&Ident> = reverse.iter().map(|reverse| &reverse.name).collect();
+    let reverse_types: Vec<&Type> = reverse.iter().map(|reverse| &reverse.ty).collect();
+    let reverses = reverse.iter().map(|reverse| {
+        let name = &reverse.name;
+        let ty = &reverse.ty;
+        let by = &reverse.by;
+        quote! {
+            let #name = match ctx.scope().link(stringify!(#name)) {
+                None => None,
+                Some(scope) => {
+                    let parent = ctx.set_scope(scope);
+                    let result = async {
+                        let many = ::airtable_proxy::reverse::fetch::<Mapped, #ty>(ctx, id, #by).await?;
+                        reverse_fields::#name(ctx, many).await
+                    }
+                    .await;
+                    ctx.set_scope(parent);
+                    match result {
+                        Ok(records) => Some(records),
+                        Err(e) => return Err(Error::Create {
+                            table: #table,
+                            id: id.clone(),
+                            field: stringify!(#name),
+                            source: Box::new(e),
+                        }),
+                    }
+                }
+            };
+        }
+    });
+    let field_types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+    let computed_types: Vec<&Type> = computed.iter().map(|computed| &computed.ty).collect();
+    let computed_names: Vec<&Ident> = computed.iter().map(|computed| &computed.name).collect();
+    let columns = fields.iter().map(|field| &field.source);
+    let unique = match fields.iter().find(|field| field.unique) {
+        Some(field) => {
+            let source = &field.source;
+            quote!(Some(#source))
+        }
+        None => quote!(None),
+    };
+
+    // the `filter` fields are compared in a formula, or for a `link`, by the
+    // IDs of the linked records
+    let filters = fields.iter().filter(|field| field.filter).map(|field| {
+        let name = &field.name;
+        let source = &field.source;
+        let ty = field.from.as_ref().unwrap_or(&field.ty);
+        if field.link {
+            quote!(filters.link(query, stringify!(#name), #source);)
+        } else {
+            quote_spanned!(ty.span()=> filters.filter::<#ty>(query, stringify!(#name), #source)?;)
+        }
+    });
+
+    let endpoints = endpoints.iter().map(|endpoint| {
+        let name = &endpoint.name;
+        let arg = &endpoint.arg;
+        let returns = match &endpoint.returns {
+            Some(returns) => quote!(#returns),
+            None => quote!(#ident),
+        };
+        let exec = &endpoint.exec;
+        let exec = if exec.is_empty() {
+            quote!()
+        } else {
+            quote!(exec = #(#exec),*;)
+        };
+        let method = endpoint.method.iter();
+        let path = match &endpoint.path {
+            Some(path) => quote!(#(#path)/ *),
+            None => quote!(#arg),
+        };
+        let query = endpoint.query.iter();
+        let body = endpoint.body.iter();
+        let keys = endpoint.keys.iter();
+        let input_display = LitStr::new(&display(quote!(#arg)), arg.span());
+        let exec_display = endpoint
+            .exec
+            .iter()
+            .map(|exec| display(quote!(#exec)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let exec_display = LitStr::new(&exec_display, name.span());
+        quote! {
+            #name (#arg) -> #returns {
+                display(#input_display, #exec_display);
+                #(method = #method;)*
+                url_path { #path }
+                #(query = #query;)*
+                #(body = #body;)*
+                #(keys = #keys;)*
+                #exec
+            }
+        }
+    });
+
+    quote! {
+        #vis mod #module {
+            #![allow(unused)]
+            use super::*;
+            use ::airtable_proxy::airtable::FetchCtx;
+            use ::airtable_proxy::error::Error;
+            use ::airtable_proxy::field_types::*;
+            use ::airtable_proxy::gen_schema::Table;
+            use ::airtable_proxy::network::request::*;
+            use ::airtable_proxy::network::response::One;
+            use ::airtable_proxy::transform::*;
+            use ::airtable_proxy::{compose, pure};
+
+            /// The fields of a record, as airtable sends them, which leaves
+            /// out empty cells, so each of them can be missing.
+            #[derive(Debug, ::airtable_proxy::serde::Deserialize)]
+            #[serde(crate = "::airtable_proxy::serde")]
+            pub struct Fields {
+                #(
+                    #[serde(rename = #sources, default)]
+                    pub #names: Option<#types>,
+                )*
+            }
+
+            /// The `Mapped` fields that come from the record's fields, before
+            /// the computed ones (which can be made from these) are added.
+            #[derive(Debug)]
+            pub struct Partial {
+                #(pub #names: #field_types,)*
+            }
+
+            pub type Mapped = super::#ident;
+
+            impl Table for Mapped {
+                const NAME: &'static str = #table;
+                const MODULE_NAME: &'static str = #module_name;
+                const TYPE_NAME: &'static str = stringify!(#ident);
+                const FIELD_NAMES: &'static [&'static str] = &[
+                    #(stringify!(#names),)* #(stringify!(#computed_names),)*
+                    #(stringify!(#reverse_names),)*
+                ];
+                const COLUMNS: &'static [(&'static str, &'static str)] = &[
+                    #((stringify!(#names), #columns),)*
+                ];
+                #(const BASE: &'static str = #base;)*
+                #(const KEY: &'static str = #key;)*
+                type Fields = Fields;
+            }
+
+            /// The `Mapped` type is its component in the spec.
+            impl ::airtable_proxy::shape::Describe for Mapped {
+                fn shape() -> ::airtable_proxy::shape::Shape {
+                    ::airtable_proxy::shape::Shape::Ref(Self::TYPE_NAME)
+                }
+            }
+
+            /// The records of the `Mapped` type have their keys renamed, and so
+            /// do the ones they link to.
+            impl ::airtable_proxy::keys::Rename for Mapped {
+                fn rename(value: &mut ::airtable_proxy::serde_json::Value, keys: ::airtable_proxy::keys::Keys) {
+                    use ::airtable_proxy::keys::rename_field;
+                    if let ::airtable_proxy::serde_json::Value::Object(object) = value {
+                        #(rename_field::<#field_types>(object, stringify!(#names), keys);)*
+                        #(rename_field::<#computed_types>(object, stringify!(#computed_names), keys);)*
+                        #(rename_field::<Option<Vec<#reverse_types>>>(object, stringify!(#reverse_names), keys);)*
+                        keys.rename_fields::<Self>(object);
+                    }
+                }
+            }
+
+            /// The shape of the `Mapped` type, for its component in the spec.
+            pub fn object() -> ::airtable_proxy::shape::Shape {
+                use ::airtable_proxy::shape::Describe;
+                ::airtable_proxy::shape::Shape::Record(vec![
+                    #((stringify!(#names), <#field_types>::shape()),)*
+                    #((stringify!(#computed_names), <#computed_types>::shape()),)*
+                    #((stringify!(#reverse_names), <Option<Vec<#reverse_types>>>::shape()),)*
+                ])
+            }
+
+            /// Creates the records of each `reverse` field, boxed, since those
+            /// can link back to this table.
+            pub mod reverse_fields {
+                use super::*;
+                #(pub fn #reverse_names(
+                    ctx: &mut FetchCtx,
+                    many: ::airtable_proxy::network::response::List<<#reverse_types as Table>::Fields>,
+                ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<#reverse_types>, Error>> + Send + '_>> {
+                    Box::pin(<#reverse_types>::create_many(ctx, many))
+                })*
+            }
+
+            /// The filters in the `query` on the `filter` fields, for the list.
+            pub fn filters(
+                query: &std::collections::HashMap<String, String>,
+            ) -> Result<::airtable_proxy::list::Filters, Error> {
+                let mut filters = ::airtable_proxy::list::Filters::default();
+                #(#filters)*
+                Ok(filters)
+            }
+
+            /// The column of the first `unique` field, which the batch looks up
+            /// the keys that aren't record IDs in.
+            pub fn unique_column() -> Option<&'static str> {
+                #unique
+            }
+
+            impl Mapped {
+                pub async fn create_one(ctx: &mut FetchCtx, one: One<Fields>) -> Result<Self, Error> {
+                    ctx.enter(Self::NAME, &one.id)?;
+                    let result = Self::hydrate(ctx, one).await;
+                    ctx.leave();
+                    result
+                }
+
+                async fn hydrate(ctx: &mut FetchCtx, one: One<Fields>) -> Result<Self, Error> {
+                    ctx.scope().check(Self::NAME, Self::FIELD_NAMES)?;
+                    let id = &one.id;
+                    #(#from_fields)*
+                    let partial = Partial { #(#creates),* };
+                    #(#from_mapped)*
+                    #(#reverses)*
+                    Ok(Self {
+                        #(#names: partial.#names,)*
+                        #(#computed_names,)*
+                        #(#reverse_names,)*
+                    })
+                }
+
+                pub async fn create_many(ctx: &mut FetchCtx, many: Vec<One<Fields>>) -> Result<Vec<Self>, Error> {
+                    let mut result = Vec::with_capacity(many.len());
+                    for one in many {
+                        result.push(Self::create_one(ctx, one).await?);
+                    }
+                    Ok(result)
+                }
+
+                pub async fn fetch_and_create_first(ctx: &mut FetchCtx, ids: Vec<String>) -> Result<Self, Error> {
+                    let params: Param<Self> = Param::new_id(ids);
+                    compose!(ctx, params, [ one, Self::create_one ])
+                }
+
+                pub async fn fetch_and_create_many(ctx: &mut FetchCtx, ids: Vec<String>) -> Result<Vec<Self>, Error> {
+                    let params: Param<Self> = Param::new_id(ids);
+                    compose!(ctx, params, [ many, Self::create_many ])
+                }
+            }
+
+            pub mod param {
+                #![allow(unused)]
+                use super::*;
+                pure!(as_id_query(id: String) -> Param<Mapped> {
+                    Param::new_query("ID".to_string(), id)
+                });
+            }
+
+            pub mod endpoints {
+                #![allow(unused)]
+                use super::*;
+                use ::airtable_proxy::ctx::Ctx;
+                use ::airtable_proxy::warp;
+                ::airtable_proxy::__gen_inner!{@endpoints #module_name, [ #(#endpoints)* ]}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snake_case_modules() {
+        assert_eq!(snake_case("Invoice"), "invoice");
+        assert_eq!(snake_case("InvoiceClient"), "invoice_client");
+        assert_eq!(snake_case("HTTPClient"), "http_client");
+        assert_eq!(snake_case("InvoiceID"), "invoice_id");
+        assert_eq!(snake_case("Invoice2020Rate"), "invoice2020_rate");
+        assert_eq!(snake_case("Invoice_Rate"), "invoice_rate");
+    }
+
+    #[test]
+    fn display_as_written() {
+        let display = |tokens: &str| display(tokens.parse().unwrap());
+        assert_eq!(display("Vec<String>"), "Vec<String>");
+        assert_eq!(display("HashMap<String, u32>"), "HashMap<String, u32>");
+        assert_eq!(display("Option<&'static str>"), "Option<&'static str>");
+        assert_eq!(display("param::as_id_query"), "param::as_id_query");
+        assert_eq!(
+            display("<Vec<u8> as Default>::default"),
+            "<Vec<u8> as Default>::default"
+        );
+    }
+}
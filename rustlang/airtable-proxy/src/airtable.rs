@@ -1,83 +1,545 @@
+use crate::config::Config;
 use crate::error::Error;
-use crate::network::cache::Cache;
-use reqwest::Url;
+use crate::expand::Scope;
+use crate::list::ListParams;
+use crate::network::cache::{Batch, Cache, Download, File, Snapshot};
+use crate::network::response::One;
+use crate::reverse::Linking;
+use crate::transform::{Attachment, Attachments};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Response, StatusCode, Url};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+use tokio::stream::StreamExt;
+use warp::hyper::body::{Body, Bytes};
 
 type Result<T> = std::result::Result<T, Error>;
 
-async fn fetch(client: reqwest::Client, url: Url, auth: &str) -> Result<Value> {
-    crate::trace!("fetch | url={}", url);
-    let response = client
-        .get(url)
-        .bearer_auth(auth)
-        .send()
-        .await
-        .map_err(Error::Req)?;
-    if response.status().is_success() {
-        response.json().await.map_err(Error::Req)
-    } else {
-        // FIXME maybe not strings later?
-        let url = format!("{}", response.url());
-        let status = response.status().to_string();
-        crate::warn!("fetch | NON-OK Response. status={} url={}", status, url);
-        Err(Error::Response { status, url })
+/// How many times, and how long to wait between, retrying failed requests.
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    retries: u32,
+    backoff: Duration,
+}
+
+/// The longest we wait before a retry.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+impl Retry {
+    /// The wait before the retry after the `attempt`, doubled for each one.
+    fn wait(self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(MAX_BACKOFF, |wait| wait.min(MAX_BACKOFF))
     }
 }
 
-fn id_url(ctx: &FetchCtx, table: &str, id: &str) -> Result<Url> {
-    let url = format!("{}/{}", ctx.config.table_url(table), id);
-    Url::parse(&url).map_err(Error::UrlParser)
+async fn fetch(client: reqwest::Client, url: Url, auth: &str, retry: Retry) -> Result<Value> {
+    let mut attempt = 0;
+    loop {
+        crate::trace!("fetch | url={} attempt={}", url, attempt);
+        let result = client.get(url.clone()).bearer_auth(auth).send().await;
+        let should_retry = match &result {
+            Ok(response) => {
+                response.status().is_server_error()
+                    || response.status() == StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(e) => e.is_timeout() || e.is_connect(),
+        };
+        if should_retry && attempt < retry.retries {
+            let wait = retry.wait(attempt);
+            crate::warn!("fetch | retrying url={} in {:?}", url, wait);
+            tokio::time::delay_for(wait).await;
+            attempt += 1;
+            continue;
+        }
+
+        let response = result.map_err(Error::Req)?;
+        return if response.status().is_success() {
+            response.json().await.map_err(Error::Req)
+        } else {
+            Err(response_error(&response))
+        };
+    }
 }
 
-fn query_url(ctx: &FetchCtx, table: &str, field: &str, value: &str) -> Result<Url> {
-    let query = format!("{{{field}}} = '{value}'", field = field, value = value);
-    Url::parse_with_params(&ctx.config.table_url(table), &[("filterByFormula", &query)])
-        .map_err(Error::UrlParser)
+/// Downloads an attachment, these urls are signed, so we don't send our key.
+///
+/// Files bigger than `max_bytes` aren't read into memory, they're streamed,
+/// which we know up front from the `Content-Length`, or once we've read that
+/// much of the file without one.
+async fn fetch_file(client: reqwest::Client, url: Url, max_bytes: u64) -> Result<Download> {
+    crate::trace!("fetch_file | url={}", url);
+    let response = client.get(url).send().await.map_err(Error::Req)?;
+    if [StatusCode::FORBIDDEN, StatusCode::GONE].contains(&response.status()) {
+        return Err(Error::Expired {
+            url: response.url().to_string(),
+        });
+    } else if !response.status().is_success() {
+        return Err(response_error(&response));
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_owned();
+    if matches!(response.content_length(), Some(len) if len > max_bytes) {
+        let body = Body::wrap_stream(response.bytes_stream());
+        return Ok(Download::Stream { content_type, body });
+    }
+    let mut read = Vec::new();
+    let mut chunks = response.bytes_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk.map_err(Error::Req)?;
+        if (read.len() + chunk.len()) as u64 > max_bytes {
+            let read = tokio::stream::iter(vec![Ok(Bytes::from(read)), Ok(chunk)]);
+            let body = Body::wrap_stream(read.chain(chunks));
+            return Ok(Download::Stream { content_type, body });
+        }
+        read.extend_from_slice(&chunk);
+    }
+    Ok(Download::File(File {
+        content_type,
+        bytes: Bytes::from(read),
+    }))
+}
+
+fn response_error(response: &Response) -> Error {
+    // FIXME maybe not strings later?
+    let url = format!("{}", response.url());
+    let status = response.status().to_string();
+    crate::warn!("fetch | NON-OK Response. status={} url={}", status, url);
+    Error::Response { status, url }
+}
+
+/// Where a table lives, `base` and `key` are names of those in the `Config`.
+#[derive(Debug, Clone, Copy)]
+pub struct Source {
+    pub base: &'static str,
+    pub key: &'static str,
+    pub table: &'static str,
+}
+
+/// Whether the `id` is an airtable record ID, `rec` and 14 letters or digits.
+pub fn is_record_id(id: &str) -> bool {
+    id.len() == 17 && id.starts_with("rec") && id[3..].bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+fn id_url(ctx: &FetchCtx, source: Source, id: &str) -> Result<Url> {
+    record_url(&ctx.config.table_url(source)?, id)
+}
+
+/// The url of the record `id` in the table at `table_url`. The `id` can come
+/// from the request, so anything but a record ID is an error, or it could be
+/// a path to some other base or table.
+fn record_url(table_url: &str, id: &str) -> Result<Url> {
+    if !is_record_id(id) {
+        return Err(Error::InvalidArgument {
+            endpoint: "record ID",
+            arg: id.to_owned(),
+        });
+    }
+    let mut url = Url::parse(table_url).map_err(Error::UrlParser)?;
+    url.path_segments_mut()
+        .map_err(|_| Error::UrlParser(url::ParseError::RelativeUrlWithCannotBeABaseBase))?
+        .push(id);
+    Ok(url)
+}
+
+fn query_url(ctx: &FetchCtx, source: Source, field: &str, value: &str) -> Result<Url> {
+    // the value comes from the request, so it's escaped, or it could be
+    // more of the formula
+    let query = format!("{{{}}} = {}", field, crate::list::quoted(value));
+    Url::parse_with_params(
+        &ctx.config.table_url(source)?,
+        &[("filterByFormula", &query)],
+    )
+    .map_err(Error::UrlParser)
+}
+
+/// A `FetchCtx` in a batch, which ends when this is dropped, even when the
+/// request is cancelled part way through, see `FetchCtx::batch`.
+pub struct InBatch<'a> {
+    ctx: &'a mut FetchCtx,
+    batch: &'a mut Batch,
+}
+
+impl Deref for InBatch<'_> {
+    type Target = FetchCtx;
+
+    fn deref(&self) -> &FetchCtx {
+        self.ctx
+    }
+}
+
+impl DerefMut for InBatch<'_> {
+    fn deref_mut(&mut self) -> &mut FetchCtx {
+        self.ctx
+    }
+}
+
+impl Drop for InBatch<'_> {
+    fn drop(&mut self) {
+        *self.batch = self.ctx.cache.end_batch();
+    }
 }
 
 #[derive(Debug)]
 pub struct FetchCtx {
-    config: crate::config::Config,
+    config: Config,
     client: reqwest::Client,
+    retry: Retry,
     pub(crate) cache: Cache,
+    /// The last payload cursor we've seen, for each webhook.
+    pub(crate) webhook_cursors: HashMap<String, u64>,
+    /// What's expanded and asked for, for the request being handled.
+    scope: Scope,
+    /// The records linking to others, found for the request being handled,
+    /// see `reverse`.
+    pub(crate) linking: Linking,
 }
 
 impl FetchCtx {
-    /// Creates a `FetchCtx` from the environment.
-    ///
-    /// Required env vars are `AIRTABLE_KEY`, and `AIRTABLE_APP`.
+    /// Creates a `FetchCtx` from the config file and environment,
+    /// see `Config::load`.
     pub fn from_env() -> Result<Self> {
-        let config = crate::config::Config::from_env()?;
+        Self::new(Config::load()?)
+    }
+
+    pub fn new(config: Config) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout())
+            .build()
+            .map_err(Error::Req)?;
         Ok(Self {
+            retry: Retry {
+                retries: config.http.retries,
+                backoff: Duration::from_millis(config.http.retry_backoff_ms),
+            },
+            cache: Cache::new(
+                config.cache.enabled,
+                config.ttl(),
+                config.cache.max_file_bytes,
+                config.cache.max_files_bytes,
+            ),
+            client,
             config,
-            cache: Cache::new(),
-            client: reqwest::Client::new(),
+            webhook_cursors: HashMap::new(),
+            scope: Scope::default(),
+            linking: Linking::new(),
         })
     }
 
-    async fn fetch<T: DeserializeOwned>(&mut self, url: Url) -> Result<T> {
+    /// The linked records `create_one` hydrates, and the fields that are asked
+    /// for, under the record it's creating.
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    /// Ends the request being handled, resetting the scope, and forgetting
+    /// what was found for it.
+    pub fn end_request(&mut self) {
+        self.scope = Scope::default();
+        self.linking.clear();
+    }
+
+    /// Replaces the scope, returning the one it was, so it can be put back.
+    pub fn set_scope(&mut self, scope: Scope) -> Scope {
+        std::mem::replace(&mut self.scope, scope)
+    }
+
+    /// Adds the record `id` of the `table` to the path of the ones being created,
+    /// see `Scope::enter`.
+    pub fn enter(&mut self, table: &'static str, id: &str) -> Result<()> {
+        self.scope.enter(table, id, self.config.server.max_depth)
+    }
+
+    pub fn leave(&mut self) {
+        self.scope.leave();
+    }
+
+    /// Puts the ctx in the `batch` until the `InBatch` is dropped. In a batch,
+    /// a record (or query) is fetched once, even when the cache is disabled,
+    /// so the records linked from several of the batch's aren't fetched again
+    /// for each. The responses go back in the `batch`, for its next key.
+    pub fn batch<'a>(&'a mut self, batch: &'a mut Batch) -> InBatch<'a> {
+        self.cache.start_batch(std::mem::take(batch));
+        InBatch { ctx: self, batch }
+    }
+
+    /// Whether `GET /_raw/{module}/{record_id}` is served, see `raw`.
+    pub fn raw_endpoint(&self) -> bool {
+        self.config.server.raw_endpoint
+    }
+
+    /// Whether `GET /cache/dump` and `POST /cache/load` are served.
+    pub fn cache_snapshots(&self) -> bool {
+        self.config.server.cache_snapshots
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn webhook_secret(&self) -> Option<&str> {
+        self.config
+            .airtable
+            .webhook_secret
+            .as_ref()
+            .map(|secret| secret.expose())
+    }
+
+    /// Reloads the keys and webhook secret, from the config file, env, and
+    /// secret files, so they can be rotated without a restart.
+    ///
+    /// Nothing else in the config can change without a restart.
+    pub fn reload_secrets(&mut self) -> Result<()> {
+        let config = Config::load()?.namespace(self.config.name())?;
+        self.config.airtable.keys = config.airtable.keys;
+        self.config.airtable.webhook_secret = config.airtable.webhook_secret;
+        Ok(())
+    }
+
+    async fn fetch<T: DeserializeOwned>(&mut self, source: Source, url: Url) -> Result<T> {
         let client = self.client.clone();
-        let key = &self.config.key;
+        let key = self.config.key(source.key)?;
+        let retry = self.retry;
         let value = self
             .cache
-            .get_or_insert_with(url, move |url| fetch(client, url, key))
+            .get_or_insert_with(url, move |url| fetch(client, url, key, retry))
             .await?;
         serde_json::from_value(value).map_err(Error::SerdeTransform)
     }
 
-    pub async fn fetch_id<T: DeserializeOwned>(&mut self, table: &str, id: &str) -> Result<T> {
-        let url = id_url(&self, table, id)?;
-        self.fetch(url).await
+    pub async fn fetch_id<T: DeserializeOwned>(&mut self, source: Source, id: &str) -> Result<T> {
+        let url = id_url(self, source, id)?;
+        self.fetch(source, url).await
     }
 
     pub async fn fetch_query<T: DeserializeOwned>(
         &mut self,
-        table: &str,
+        source: Source,
         field: &str,
         value: &str,
     ) -> Result<T> {
-        let url = query_url(&self, table, field, value)?;
-        self.fetch(url).await
+        let url = query_url(self, source, field, value)?;
+        self.fetch(source, url).await
+    }
+
+    /// Fetches a page of the records in the table, see `list::ListParams`.
+    pub async fn fetch_list<T: DeserializeOwned>(
+        &mut self,
+        source: Source,
+        params: &ListParams,
+    ) -> Result<T> {
+        let url = Url::parse_with_params(&self.config.table_url(source)?, params.pairs())
+            .map_err(Error::UrlParser)?;
+        self.fetch(source, url).await
+    }
+
+    /// Loads the cache snapshot, and the webhooks' cursors, if one is
+    /// configured and it's been saved before.
+    pub fn load_snapshot(&mut self) -> Result<()> {
+        let path = match &self.config.cache.snapshot_path {
+            Some(path) if std::path::Path::new(path).exists() => path,
+            _ => return Ok(()),
+        };
+        let snapshot = std::fs::read_to_string(path).map_err(|source| Error::Snapshot {
+            path: path.clone(),
+            source,
+        })?;
+        let mut snapshot: Snapshot =
+            serde_json::from_str(&snapshot).map_err(Error::SerdeTransform)?;
+        self.webhook_cursors
+            .extend(std::mem::take(&mut snapshot.webhook_cursors));
+        let restored = self.cache.restore(snapshot);
+        crate::info!("Loaded {} cached responses from {}", restored, path);
+        Ok(())
+    }
+
+    /// Saves the cache, and the webhooks' cursors, to the snapshot, if one is
+    /// configured, and the cache is enabled, or it'd be left empty.
+    pub fn flush(&self) -> Result<()> {
+        let path = match &self.config.cache.snapshot_path {
+            Some(path) if self.config.cache.enabled => path,
+            _ => return Ok(()),
+        };
+        let mut snapshot = self.cache.snapshot();
+        snapshot.webhook_cursors = self.webhook_cursors.clone();
+        let contents = serde_json::to_string(&snapshot).map_err(Error::SerdeTransform)?;
+        std::fs::write(path, contents).map_err(|source| Error::Snapshot {
+            path: path.clone(),
+            source,
+        })?;
+        crate::info!(
+            "Saved {} cached responses to {}",
+            snapshot.responses.len(),
+            path
+        );
+        Ok(())
+    }
+
+    /// Fetches the `n`th file in the attachment `field` of the record `id`.
+    pub(crate) async fn fetch_attachment(
+        &mut self,
+        source: Source,
+        id: &str,
+        field: &str,
+        n: usize,
+    ) -> Result<Download> {
+        let attachment = self.attachment(source, id, field, n).await?;
+        match self.fetch_file(attachment).await {
+            // the urls in a cached record expire, so it's fetched again
+            Err(Error::Expired { .. }) => {
+                self.cache.remove(&id_url(self, source, id)?);
+                let attachment = self.attachment(source, id, field, n).await?;
+                self.fetch_file(attachment).await
+            }
+            result => result,
+        }
+    }
+
+    async fn attachment(
+        &mut self,
+        source: Source,
+        id: &str,
+        field: &str,
+        n: usize,
+    ) -> Result<Attachment> {
+        let record: One<HashMap<String, Value>> = self.fetch_id(source, id).await?;
+        let attachments: Attachments = match record.fields.get(field) {
+            Some(value) => serde_json::from_value(value.clone()).map_err(Error::SerdeTransform)?,
+            None => vec![],
+        };
+        attachments
+            .into_iter()
+            .nth(n)
+            .ok_or_else(|| Error::NotFound {
+                table: source.table,
+                key: format!("{}.{}[{}]", id, field, n),
+            })
+    }
+
+    async fn fetch_file(&mut self, attachment: Attachment) -> Result<Download> {
+        let url = Url::parse(&attachment.url).map_err(Error::UrlParser)?;
+        let client = self.client.clone();
+        let max_bytes = self.config.cache.max_file_bytes;
+        self.cache
+            .get_or_insert_file_with(attachment.id, move || fetch_file(client, url, max_bytes))
+            .await
+    }
+
+    /// Fetches the page of change payloads for the `webhook` in the `base`
+    /// (by ID) starting at `cursor`, these are never cached.
+    ///
+    /// This uses the key named the same as the base, if there is one.
+    pub(crate) async fn fetch_webhook_payloads<T: DeserializeOwned>(
+        &self,
+        base: &str,
+        webhook: &str,
+        cursor: u64,
+    ) -> Result<T> {
+        let key = match self.config.base_name(base) {
+            Some(name) => self.config.airtable.keys.get(name).map(|key| key.expose()),
+            None => None,
+        };
+        let key = match key {
+            Some(key) => key,
+            None => self.config.key(crate::config::DEFAULT)?,
+        };
+        let url = Url::parse_with_params(
+            &self.config.webhook_payloads_url(base, webhook),
+            &[("cursor", cursor.to_string())],
+        )
+        .map_err(Error::UrlParser)?;
+        let value = fetch(self.client.clone(), url, key, self.retry).await?;
+        serde_json::from_value(value).map_err(Error::SerdeTransform)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE_URL: &str = "https://api.airtable.com/v0/appXXXXXXXXXXXXXX/Invoice";
+
+    #[test]
+    fn record_ids() {
+        assert!(is_record_id("recABCDEFGH123456"));
+        assert!(!is_record_id("recABCDEFGH12345"));
+        assert!(!is_record_id("recABCDEFGH1234567"));
+        assert!(!is_record_id("appABCDEFGH123456"));
+        assert!(!is_record_id("recABCDEFGH12345/"));
+        assert!(!is_record_id("recABCDEFGH1234é"));
+    }
+
+    #[test]
+    fn record_url_is_in_the_table() {
+        let url = record_url(TABLE_URL, "recABCDEFGH123456").unwrap();
+        assert_eq!(url.as_str(), format!("{}/recABCDEFGH123456", TABLE_URL));
+    }
+
+    #[test]
+    fn record_url_rejects_other_paths() {
+        for id in &[
+            "rec/../../appOTHER/Secrets/recY",
+            "rec?filterByFormula=1",
+            "rec#fragment",
+            "..",
+            "",
+        ] {
+            match record_url(TABLE_URL, id) {
+                Err(Error::InvalidArgument { arg, .. }) => assert_eq!(&arg, id),
+                result => panic!("{:?} was {:?}", id, result),
+            }
+        }
+    }
+
+    /// Serves `/small`, `/big` with a `Content-Length`, and `/chunked`, big
+    /// and without one, returning the url they're under.
+    fn files() -> String {
+        use warp::Filter;
+        let small = warp::path!("small").map(|| "small");
+        let big = warp::path!("big").map(|| "big".repeat(10));
+        let chunked = warp::path!("chunked").map(|| {
+            let chunks = (0..10).map(|_| Ok::<_, std::io::Error>("chunk"));
+            warp::http::Response::new(Body::wrap_stream(tokio::stream::iter(chunks)))
+        });
+        let (address, server) =
+            warp::serve(small.or(big).or(chunked)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}/", address)
+    }
+
+    async fn download(url: &str) -> (bool, Vec<u8>) {
+        let url = Url::parse(url).unwrap();
+        match fetch_file(reqwest::Client::new(), url, 8).await.unwrap() {
+            Download::File(file) => (false, file.bytes.to_vec()),
+            Download::Stream { body, .. } => {
+                let bytes = warp::hyper::body::to_bytes(body).await.unwrap();
+                (true, bytes.to_vec())
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn big_files_are_streamed() {
+        let url = files();
+        assert_eq!(
+            download(&format!("{}small", url)).await,
+            (false, b"small".to_vec())
+        );
+        assert_eq!(
+            download(&format!("{}big", url)).await,
+            (true, "big".repeat(10).into_bytes())
+        );
+        assert_eq!(
+            download(&format!("{}chunked", url)).await,
+            (true, "chunk".repeat(10).into_bytes())
+        );
     }
 }
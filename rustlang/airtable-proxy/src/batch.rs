@@ -0,0 +1,95 @@
+//! Getting many records of a table at once, the `POST /{module}/batch` every
+//! table gets.
+//!
+//! The body is a JSON array of keys, each a record ID, or, for a table with a
+//! `unique;` field, a value of that field, like an invoice number. The reply
+//! is an object with each key, and its `{"record": ...}`, or its
+//! `{"error": ...}`, so one missing record doesn't fail the others. The keys
+//! are fetched in one `FetchCtx::batch`, so the records they link to are only
+//! fetched once, but the server only locks the ctx for one key at a time, so
+//! a big batch doesn't hold up the other requests.
+
+use crate::airtable::{is_record_id, FetchCtx};
+use crate::error::Error;
+use crate::expand::Scope;
+use crate::gen_schema::Table;
+use crate::keys::{Keys, Rename};
+use crate::network::response::{Many, One};
+use crate::openapi::{Operation, Segment};
+use crate::shape::{Describe, Shape};
+use serde_json::{json, Value};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The most keys in one batch.
+pub const MAX_KEYS: usize = 100;
+
+/// Airtable's record IDs all start with this.
+const RECORD_ID_PREFIX: &str = "rec";
+
+/// Checks there are some `keys`, and not too many, that the ones that start
+/// like a record ID are one, and the asked for fields in the `scope` are some
+/// of `U`'s, before any of them are fetched.
+pub fn check<U: Table>(scope: &Scope, keys: &[String]) -> Result<()> {
+    if keys.is_empty() || keys.len() > MAX_KEYS {
+        return Err(Error::InvalidQuery {
+            param: "keys".to_owned(),
+            value: keys.len().to_string(),
+            expected: format!("from 1 to {} record IDs or keys", MAX_KEYS),
+        });
+    }
+    let not_an_id = keys
+        .iter()
+        .find(|key| key.starts_with(RECORD_ID_PREFIX) && !is_record_id(key));
+    if let Some(key) = not_an_id {
+        return Err(Error::InvalidArgument {
+            endpoint: "batch",
+            arg: key.clone(),
+        });
+    }
+    scope.check(U::NAME, U::FIELD_NAMES)
+}
+
+/// Fetches the record for a `key` of the batch, by its ID, or its value in
+/// the `unique` column, when `U` has one.
+pub async fn fetch<U: Table>(
+    ctx: &mut FetchCtx,
+    key: &str,
+    unique: Option<&'static str>,
+) -> Result<One<U::Fields>> {
+    let not_found = || Error::NotFound {
+        table: U::NAME,
+        key: key.to_owned(),
+    };
+    if key.starts_with(RECORD_ID_PREFIX) {
+        return crate::raw::fetch::<U, _>(ctx, key).await;
+    }
+    let column = unique.ok_or_else(not_found)?;
+    let many: Many<U::Fields> = ctx.fetch_query(U::SOURCE, column, key).await?;
+    many.records.into_iter().next().ok_or_else(not_found)
+}
+
+/// What the reply has for one key, its record of `U`, rendered in the
+/// `scope`, or its error, with the ones under it.
+pub fn entry<U: Rename>(scope: &Scope, result: Result<Value>) -> Value {
+    match result {
+        Ok(record) => json!({ "record": scope.render::<U>(record, Keys::Fields) }),
+        Err(e) => json!({ "error": e.chain() }),
+    }
+}
+
+/// The batch of `U`, for the spec.
+pub fn operation<U: Table + Describe>() -> Operation {
+    let path = vec![Segment::Literal(U::MODULE_NAME), Segment::Literal("batch")];
+    let entry = Shape::AnyOf(vec![
+        Shape::Object(vec![("record", U::shape())]),
+        Shape::Object(vec![("error", Shape::String(None))]),
+    ]);
+    Operation {
+        method: "POST",
+        body: Some(Vec::<String>::shape()),
+        input: Some("Vec<String>"),
+        exec: Some("batch::fetch, create_one"),
+        ..Operation::endpoint::<U>("batch", path, Shape::Map(Box::new(entry)))
+    }
+}
@@ -0,0 +1,357 @@
+//! Builds the proxy's `warp::Filter`, so it can be mounted inside another
+//! warp service instead of only being run by `start_proxy!`.
+//!
+//! Schemas are mounted at the root with `schema`, or each under the prefix of
+//! a namespace with `namespace`, where they get their own `FetchCtx`.
+//!
+//! ```ignore
+//! let config = airtable_proxy::config::Config::load()?;
+//! let proxy = ProxyBuilder::new(config)
+//!     .namespace::<invoices::gen::Schema>("invoices")
+//!     .namespace::<timesheets::gen::Schema>("timesheets")
+//!     .middleware(|route| my_auth().and(route).boxed())
+//!     .build()?;
+//! let routes = warp::path("api").and(proxy.route).or(my_other_routes);
+//! warp::serve(routes).run(address).await;
+//! ```
+//!
+//! Setting up the runtime and the logger is left to the caller.
+
+use crate::airtable::FetchCtx;
+use crate::config::{Config, ConfigError, ConfigErrors};
+use crate::ctx::{wrap_ctx, Ctx};
+use crate::error::Error;
+use crate::gen_schema::{Endpoints, Sources};
+use crate::openapi::Operation;
+use crate::shape::Shape;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::Arc;
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Rejection, Reply};
+
+/// A type erased route, like the ones a `Schema` generates.
+pub type Route = BoxedFilter<(Box<dyn Reply>,)>;
+
+/// Boxes any route into a `Route`.
+pub fn boxed<F, R>(route: F) -> Route
+where
+    F: Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    R: Reply + 'static,
+{
+    route.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed()
+}
+
+/// What `gen_airtable_schema!` generates for a schema (as `gen::Schema`),
+/// so it can be mounted with a `ProxyBuilder`.
+pub trait Schema {
+    const SOURCES: Sources;
+    const ENDPOINTS: Endpoints;
+    fn route(ctx: Ctx) -> Route;
+    /// The routes, for the OpenAPI spec.
+    fn operations() -> Vec<Operation>;
+    /// The `Mapped` types, by name, for the OpenAPI spec.
+    fn components() -> Vec<(&'static str, Shape)>;
+}
+
+type Middleware = Box<dyn Fn(Route) -> Route>;
+
+/// A schema to mount, in a namespace, or at the root without one.
+struct Mount {
+    namespace: Option<&'static str>,
+    sources: Sources,
+    endpoints: Endpoints,
+    route: fn(Ctx) -> Route,
+    operations: fn() -> Vec<Operation>,
+    components: fn() -> Vec<(&'static str, Shape)>,
+}
+
+pub struct ProxyBuilder {
+    config: Config,
+    mounts: Vec<Mount>,
+    middleware: Vec<Middleware>,
+}
+
+/// The built proxy, its `route` can be served or composed with other filters.
+pub struct Proxy {
+    pub mounted: Vec<Mounted>,
+    /// Every route of the `route`, as `GET /_routes` lists them.
+    pub routes: Vec<RouteInfo>,
+    pub route: Route,
+}
+
+/// A route the proxy serves, what it takes, and for a generated endpoint,
+/// the table it reads and the `exec` chain it runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteInfo {
+    pub namespace: Option<&'static str>,
+    pub module: Option<&'static str>,
+    pub endpoint: &'static str,
+    pub method: &'static str,
+    /// The path, with `{name}` for each of its parameters, like `/invoice/{arg}`.
+    pub path: String,
+    pub input: Option<&'static str>,
+    pub exec: Option<&'static str>,
+    pub table: Option<&'static str>,
+}
+
+impl RouteInfo {
+    /// The route of the `operation`, of a schema mounted under the `prefix`.
+    fn new(namespace: Option<&'static str>, prefix: &str, operation: &Operation) -> Self {
+        let (path, _) = operation.template();
+        Self {
+            namespace,
+            module: operation.module,
+            endpoint: operation.name,
+            method: operation.method,
+            path: match prefix {
+                "" => path,
+                prefix => format!("/{}{}", prefix, path),
+            },
+            input: operation.input,
+            exec: operation.exec,
+            table: operation.table,
+        }
+    }
+}
+
+/// Where the schemas of a namespace (or the root) are mounted, and the `ctx`
+/// they share, which is where their cache lives.
+pub struct Mounted {
+    pub namespace: Option<&'static str>,
+    /// The path it's mounted under, empty for the root.
+    pub prefix: String,
+    pub ctx: Ctx,
+    sources: Vec<Sources>,
+    endpoints: Vec<Endpoints>,
+    pub(crate) operations: Vec<Operation>,
+    pub(crate) components: Vec<(&'static str, Shape)>,
+}
+
+impl Mounted {
+    /// The module and endpoint names of the schemas mounted here.
+    pub fn endpoints(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.endpoints.iter().flat_map(|endpoints| {
+            endpoints
+                .iter()
+                .flat_map(|(module, names)| names.iter().map(move |name| (*module, *name)))
+        })
+    }
+
+    /// The tables of the schemas mounted here.
+    pub fn sources(&self) -> impl Iterator<Item = &(&'static str, crate::airtable::Source)> + '_ {
+        self.sources.iter().flat_map(|sources| sources.iter())
+    }
+}
+
+impl Proxy {
+    /// Each of the `FetchCtx`s, one per namespace.
+    pub fn ctxs(&self) -> impl Iterator<Item = &Ctx> {
+        self.mounted.iter().map(|mounted| &mounted.ctx)
+    }
+}
+
+impl ProxyBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            mounts: vec![],
+            middleware: vec![],
+        }
+    }
+
+    /// Mounts the routes of the schema `S` at the root, the schemas mounted
+    /// there all share one `FetchCtx`.
+    pub fn schema<S: Schema>(self) -> Self {
+        self.mount::<S>(None)
+    }
+
+    /// Mounts the routes of the schema `S` under the prefix of the namespace
+    /// `name`, with its own `FetchCtx` made from `Config::namespace`, so its own
+    /// credentials and cache.
+    pub fn namespace<S: Schema>(self, name: &'static str) -> Self {
+        self.mount::<S>(Some(name))
+    }
+
+    fn mount<S: Schema>(mut self, namespace: Option<&'static str>) -> Self {
+        self.mounts.push(Mount {
+            namespace,
+            sources: S::SOURCES,
+            endpoints: S::ENDPOINTS,
+            route: S::route,
+            operations: S::operations,
+            components: S::components,
+        });
+        self
+    }
+
+    /// Wraps the combined routes, in the order they're added, like with
+    /// auth, logging, or extra endpoints.
+    pub fn middleware<F: Fn(Route) -> Route + 'static>(mut self, middleware: F) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Validates the config against the schemas, creates the `FetchCtx` of
+    /// each namespace (loading their cache snapshots), and combines the routes,
+    /// along with `GET /_routes`, which lists them, and `GET /openapi.json`.
+    pub fn build(self) -> Result<Proxy, Error> {
+        let mut namespaces: Vec<Option<&'static str>> = vec![];
+        for mount in &self.mounts {
+            if !namespaces.contains(&mount.namespace) {
+                namespaces.push(mount.namespace);
+            }
+        }
+
+        let mut problems = vec![];
+        let mut mounted = vec![];
+        let mut routes = vec![];
+        for namespace in namespaces {
+            let mounts: Vec<&Mount> = self
+                .mounts
+                .iter()
+                .filter(|mount| mount.namespace == namespace)
+                .collect();
+            let config = match self.config.namespace(namespace) {
+                Ok(config) => config,
+                Err(e) => {
+                    problems.extend(config_problems(e)?);
+                    continue;
+                }
+            };
+            for mount in &mounts {
+                if let Err(e) = config.validate_sources(mount.sources) {
+                    problems.extend(config_problems(e)?);
+                }
+            }
+            if !problems.is_empty() {
+                continue;
+            }
+
+            let mut ctx = FetchCtx::new(config)?;
+            ctx.load_snapshot()?;
+            let ctx = wrap_ctx(ctx);
+            let prefix = match namespace {
+                Some(name) => self.config.prefix(name).to_owned(),
+                None => String::new(),
+            };
+            for mount in &mounts {
+                routes.push(prefixed(&prefix, (mount.route)(ctx.clone())));
+            }
+            mounted.push(Mounted {
+                namespace,
+                prefix,
+                ctx,
+                sources: mounts.iter().map(|mount| mount.sources).collect(),
+                endpoints: mounts.iter().map(|mount| mount.endpoints).collect(),
+                // `GET /_raw/...` is only in the spec when it's served
+                operations: mounts
+                    .iter()
+                    .flat_map(|mount| (mount.operations)())
+                    .filter(|op| {
+                        self.config.server.raw_endpoint
+                            || !(op.module.is_some() && op.name == "raw")
+                    })
+                    .collect(),
+                components: mounts
+                    .iter()
+                    .flat_map(|mount| (mount.components)())
+                    .collect(),
+            });
+        }
+        if mounted.is_empty() && problems.is_empty() {
+            problems.push(ConfigError::Invalid {
+                key: "schema".to_owned(),
+                message: "the proxy needs at least one".to_owned(),
+            });
+        }
+        if !problems.is_empty() {
+            return Err(Error::Config {
+                problems: ConfigErrors(problems),
+            });
+        }
+
+        let docs_ui = self.config.server.docs_ui;
+        let mut listed: Vec<RouteInfo> = crate::openapi::proxy_operations(docs_ui)
+            .iter()
+            .map(|operation| RouteInfo::new(None, "", operation))
+            .collect();
+        for mounted in &mounted {
+            listed.extend(
+                mounted
+                    .operations
+                    .iter()
+                    .map(|operation| RouteInfo::new(mounted.namespace, &mounted.prefix, operation)),
+            );
+        }
+
+        let route = listing(listed.clone())
+            .or(crate::openapi::route(&mounted, docs_ui))
+            .unify()
+            .boxed();
+        let route = routes
+            .into_iter()
+            .fold(route, |route, next| route.or(next).unify().boxed());
+        let route = self
+            .middleware
+            .iter()
+            .fold(route, |route, middleware| middleware(route));
+
+        Ok(Proxy {
+            mounted,
+            routes: listed,
+            route,
+        })
+    }
+}
+
+/// The problems in a `Error::Config`, so those of every namespace can be
+/// reported together, any other error is returned as is.
+fn config_problems(e: Error) -> Result<Vec<ConfigError>, Error> {
+    match e {
+        Error::Config { problems } => Ok(problems.0),
+        e => Err(e),
+    }
+}
+
+/// Mounts the `route` under the `prefix`, which can have several segments.
+fn prefixed(prefix: &str, route: Route) -> Route {
+    prefix
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .rev()
+        .fold(route, |route, segment| {
+            warp::path(segment.to_owned()).and(route).boxed()
+        })
+}
+
+/// `GET /_routes`, the routes of every namespace, and the proxy's own.
+fn listing(routes: Vec<RouteInfo>) -> Route {
+    boxed(
+        warp::path("_routes")
+            .and(warp::path::end())
+            .and(warp::get())
+            .map(move || warp::reply::json(&routes)),
+    )
+}
+
+/// The `route`, replying to the requests none of it matches with a JSON 404
+/// that lists the `routes`. It's for the top of a server, since no route
+/// after it is tried.
+pub fn with_not_found(route: Route, routes: Vec<RouteInfo>) -> Route {
+    let routes = Arc::new(routes);
+    boxed(route.recover(move |rejection: Rejection| {
+        let routes = routes.clone();
+        async move {
+            if !rejection.is_not_found() {
+                return Err(rejection);
+            }
+            let body = json!({ "error": "No route matches the request", "routes": *routes });
+            Ok(warp::reply::with_status(
+                warp::reply::json(&body),
+                StatusCode::NOT_FOUND,
+            ))
+        }
+    }))
+}
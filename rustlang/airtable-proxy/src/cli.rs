@@ -0,0 +1,543 @@
+//! The command line interface of the proxy binary that `start_proxy!` generates.
+//!
+//! - `serve` (the default) starts the server,
+//! - `get [--namespace <name>] <module> <endpoint> <arg>` renders one
+//!   endpoint to stdout,
+//! - `check` validates the config against the schema,
+//! - `types [--format typescript|json-schema] [path]` writes the types of the
+//!   `Mapped` records, for the clients of the proxy,
+//! - `cache dump [path]` and `cache load <path>` read, and add to, the
+//!   `cache.snapshot_path` (of a `--namespace`), which the server loads
+//!   when it starts.
+
+use crate::config::Config;
+use crate::error::Error;
+use crate::gen_schema::{Endpoints, Sources};
+use crate::network::cache::Snapshot;
+use crate::shape::Shape;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use serde_json::{json, Map};
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+/// The `(namespace, tables, endpoints)` of each schema the binary serves,
+/// the namespace is `None` for a schema at the root.
+pub type Namespaces = &'static [(Option<&'static str>, Sources, Endpoints)];
+
+/// The `(namespace, components)` of each schema, the `Mapped` types by name.
+pub type Components = Vec<(Option<&'static str>, Vec<(&'static str, Shape)>)>;
+
+/// What `types` writes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    TypeScript,
+    JsonSchema,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Serve {
+        host: Option<String>,
+        port: Option<u16>,
+        log: Option<String>,
+    },
+    Get {
+        namespace: Option<String>,
+        module: String,
+        endpoint: String,
+        arg: String,
+        query: crate::expand::Query,
+        body: Option<String>,
+    },
+    Check,
+    Types {
+        format: Format,
+        namespace: Option<String>,
+        path: Option<String>,
+    },
+    CacheDump {
+        namespace: Option<String>,
+        path: Option<String>,
+    },
+    CacheLoad {
+        namespace: Option<String>,
+        path: String,
+    },
+}
+
+fn endpoints_help(namespaces: Namespaces) -> String {
+    let mut help = String::from("ENDPOINTS (for get):\n");
+    for (namespace, _, endpoints) in namespaces {
+        let flag = match namespace {
+            Some(namespace) => format!("--namespace {} ", namespace),
+            None => String::new(),
+        };
+        for (module, names) in endpoints.iter() {
+            for name in names.iter() {
+                help.push_str(&format!("    {}{} {}\n", flag, module, name));
+            }
+        }
+    }
+    help
+}
+
+fn namespace_arg<'a>() -> Arg<'a, 'a> {
+    Arg::with_name("namespace")
+        .long("namespace")
+        .takes_value(true)
+        .help("The namespace whose cache it is, when the proxy serves several")
+}
+
+fn app<'a>(help: &'a str) -> App<'a, 'a> {
+    App::new("invoice-proxy")
+        .setting(AppSettings::VersionlessSubcommands)
+        .after_help(help)
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Starts the proxy server, this is the default")
+                .arg(
+                    Arg::with_name("host")
+                        .long("host")
+                        .takes_value(true)
+                        .help("The address to listen on, like 127.0.0.1:3000"),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .takes_value(true)
+                        .help("The port to listen on, overrides the port of the host"),
+                )
+                .arg(
+                    Arg::with_name("log")
+                        .long("log")
+                        .takes_value(true)
+                        .help("The env_logger filter, like info or airtable_proxy=debug"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Renders the JSON for one endpoint to stdout, without a server")
+                .arg(
+                    Arg::with_name("namespace")
+                        .long("namespace")
+                        .takes_value(true)
+                        .help("The namespace of the module, the first with it by default"),
+                )
+                .arg(Arg::with_name("module").required(true))
+                .arg(Arg::with_name("endpoint").required(true))
+                .arg(
+                    Arg::with_name("arg")
+                        .required(true)
+                        .help("The path after the module, and its query string, like 12 or year/2020?paid=true"),
+                )
+                .arg(
+                    Arg::with_name("expand")
+                        .long("expand")
+                        .takes_value(true)
+                        .help("The linked records to hydrate, like client,items.rate.unit"),
+                )
+                .arg(
+                    Arg::with_name("fields")
+                        .long("fields")
+                        .takes_value(true)
+                        .help("The fields to render, like number,total,client.company"),
+                )
+                .arg(
+                    Arg::with_name("keys")
+                        .long("keys")
+                        .takes_value(true)
+                        .possible_values(&["fields", "source", "camelCase", "snake_case"])
+                        .help("The keys of the records, the fields, their airtable columns, or in a case"),
+                )
+                .arg(
+                    Arg::with_name("body")
+                        .long("body")
+                        .takes_value(true)
+                        .help("The JSON body, for endpoints that take one"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Validates the configuration, and that it has what the schema needs"),
+        )
+        .subcommand(
+            SubCommand::with_name("types")
+                .about("Writes the types of the records the endpoints reply with, to a file or stdout")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["typescript", "json-schema"])
+                        .default_value("typescript"),
+                )
+                .arg(
+                    Arg::with_name("namespace")
+                        .long("namespace")
+                        .takes_value(true)
+                        .help("The namespace whose types to write, all of them by default"),
+                )
+                .arg(Arg::with_name("path")),
+        )
+        .subcommand(
+            SubCommand::with_name("cache")
+                .about("Reads or adds to the cache snapshot the proxy loads at startup")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("dump")
+                        .about("Writes the cache snapshot to a file, or stdout")
+                        .arg(namespace_arg())
+                        .arg(Arg::with_name("path")),
+                )
+                .subcommand(
+                    SubCommand::with_name("load")
+                        .about("Adds the responses in a snapshot file to the cache snapshot")
+                        .arg(namespace_arg())
+                        .arg(Arg::with_name("path").required(true)),
+                ),
+        )
+}
+
+fn value(matches: &ArgMatches, name: &str) -> Option<String> {
+    matches.value_of(name).map(|value| value.to_owned())
+}
+
+impl Command {
+    /// Parses the command from the process' args, exiting with the usage
+    /// when they aren't valid.
+    pub fn parse(namespaces: Namespaces) -> Self {
+        let help = endpoints_help(namespaces);
+        let matches = app(&help).get_matches();
+        match matches.subcommand() {
+            ("get", Some(get)) => Command::Get {
+                namespace: value(get, "namespace"),
+                module: value(get, "module").unwrap_or_default(),
+                endpoint: value(get, "endpoint").unwrap_or_default(),
+                arg: value(get, "arg").unwrap_or_default(),
+                query: crate::expand::Query {
+                    expand: value(get, "expand"),
+                    fields: value(get, "fields"),
+                    keys: value(get, "keys"),
+                },
+                body: value(get, "body"),
+            },
+            ("check", _) => Command::Check,
+            ("types", Some(types)) => Command::Types {
+                format: match types.value_of("format") {
+                    Some("json-schema") => Format::JsonSchema,
+                    _ => Format::TypeScript,
+                },
+                namespace: value(types, "namespace"),
+                path: value(types, "path"),
+            },
+            ("cache", Some(cache)) => match cache.subcommand() {
+                ("load", Some(load)) => Command::CacheLoad {
+                    namespace: value(load, "namespace"),
+                    path: value(load, "path").unwrap_or_default(),
+                },
+                (_, dump) => Command::CacheDump {
+                    namespace: dump.and_then(|dump| value(dump, "namespace")),
+                    path: dump.and_then(|dump| value(dump, "path")),
+                },
+            },
+            (_, serve) => Command::Serve {
+                host: serve.and_then(|serve| value(serve, "host")),
+                port: serve.and_then(|serve| {
+                    serve.value_of("port").map(|port| {
+                        port.parse().unwrap_or_else(|_| {
+                            clap::Error::value_validation_auto(format!(
+                                "The port {} is not a valid port",
+                                port
+                            ))
+                            .exit()
+                        })
+                    })
+                }),
+                log: serve.and_then(|serve| value(serve, "log")),
+            },
+        }
+    }
+}
+
+/// Loads the config, overridden by the flags given to `serve`, and starts the
+/// logger, the `--log` flag wins over the config and env.
+pub fn load_config(host: Option<String>, port: Option<u16>, log: Option<String>) -> Result<Config> {
+    let mut config = Config::load()?;
+    if let Some(host) = host {
+        config.server.host = host;
+    }
+    if let Some(port) = port {
+        if let Ok(mut address) = config.server.host.parse::<std::net::SocketAddr>() {
+            address.set_port(port);
+            config.server.host = address.to_string();
+        }
+    }
+    pretty_env_logger::formatted_builder()
+        .parse_filters(&log.unwrap_or_else(|| config.log_filters()))
+        .init();
+    Ok(config)
+}
+
+/// The namespace `get` runs the `module` in, the one it's given, or else
+/// the first with a module by that name, `None` is the root.
+pub fn namespace_of(
+    namespaces: Namespaces,
+    namespace: Option<&str>,
+    module: &str,
+) -> Result<Option<&'static str>> {
+    let names = namespaces.iter().filter_map(|(name, _, _)| *name);
+    if let Some(namespace) = namespace {
+        let valid: Vec<&'static str> = names.collect();
+        return match valid.iter().find(|name| **name == namespace) {
+            Some(name) => Ok(Some(*name)),
+            None if valid.is_empty() => {
+                Err(format!("there's no namespace {}, the proxy has none", namespace).into())
+            }
+            None => Err(format!(
+                "there's no namespace {}, expected one of: {}",
+                namespace,
+                valid.join(", ")
+            )
+            .into()),
+        };
+    }
+    Ok(namespaces
+        .iter()
+        .find(|(_, _, endpoints)| endpoints.iter().any(|(name, _)| *name == module))
+        .and_then(|(name, _, _)| *name))
+}
+
+/// Prints the JSON `value` to stdout.
+pub fn print(value: &serde_json::Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Reports everything that's wrong with the `config` of each namespace, and
+/// what the tables and endpoints in the schemas are when nothing is.
+pub fn check(config: std::result::Result<Config, Error>, namespaces: Namespaces) -> Result<()> {
+    let mut problems = vec![];
+    let mut checked = |result: std::result::Result<(), Error>| match result {
+        Err(Error::Config { problems: found }) => {
+            problems.extend(found.0);
+            Ok(())
+        }
+        result => result,
+    };
+    if let Ok(config) = &config {
+        for (namespace, sources, _) in namespaces {
+            checked(
+                config
+                    .namespace(*namespace)
+                    .and_then(|config| config.validate_sources(sources)),
+            )?;
+        }
+    }
+    checked(config.map(|_| ()))?;
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("error: {}", problem);
+        }
+        return Err("the configuration is not valid".into());
+    }
+
+    for (namespace, sources, _) in namespaces {
+        if let Some(namespace) = namespace {
+            println!("namespace {}", namespace);
+        }
+        for (module, source) in sources.iter() {
+            println!(
+                "table {} (\"{}\") in base {} with key {}",
+                module, source.table, source.base, source.key
+            );
+        }
+    }
+    print!("{}", endpoints_help(namespaces));
+    println!("ok");
+    Ok(())
+}
+
+/// The TypeScript interfaces of the `components`, each namespace's in a
+/// TypeScript namespace by its name.
+fn typescript(components: &Components) -> String {
+    let mut ts = String::from("// Generated by `types`, from the schema of the proxy.\n");
+    for (namespace, shapes) in components {
+        let indent = namespace.map_or(0, |_| 1);
+        let pad = "  ".repeat(indent);
+        if let Some(namespace) = namespace {
+            ts.push_str(&format!("\nexport namespace {} {{\n", namespace));
+        }
+        for (name, shape) in shapes {
+            ts.push('\n');
+            ts.push_str(&match shape {
+                Shape::Object(_) | Shape::Record(_) => format!(
+                    "{}export interface {} {}\n",
+                    pad,
+                    name,
+                    shape.typescript(indent)
+                ),
+                shape => format!(
+                    "{}export type {} = {};\n",
+                    pad,
+                    name,
+                    shape.typescript(indent)
+                ),
+            });
+        }
+        if namespace.is_some() {
+            ts.push_str("}\n");
+        }
+    }
+    ts
+}
+
+/// The JSON Schema of the `components`, under `$defs`, each namespace's
+/// prefixed with its name, like in the OpenAPI spec.
+fn json_schema(components: &Components) -> serde_json::Value {
+    let mut defs = Map::new();
+    for (namespace, shapes) in components {
+        let scope = match namespace {
+            Some(namespace) => format!("{}.", namespace),
+            None => String::new(),
+        };
+        let refs = format!("#/$defs/{}", scope);
+        for (name, shape) in shapes {
+            defs.insert(format!("{}{}", scope, name), shape.schema(&refs));
+        }
+    }
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$defs": defs,
+    })
+}
+
+/// Writes the types of the `components` (of the `namespace`) to `path`, or stdout.
+pub fn types(
+    mut components: Components,
+    format: Format,
+    namespace: Option<String>,
+    path: Option<String>,
+) -> Result<()> {
+    if let Some(namespace) = namespace {
+        components.retain(|(name, _)| *name == Some(namespace.as_str()));
+        if components.is_empty() {
+            return Err(format!("there's no namespace {}", namespace).into());
+        }
+        // one namespace's types are written like a schema at the root
+        components[0].0 = None;
+    }
+    let types = match format {
+        Format::TypeScript => typescript(&components),
+        Format::JsonSchema => serde_json::to_string_pretty(&json_schema(&components))?,
+    };
+    match path {
+        Some(path) => std::fs::write(path, types)?,
+        None => println!("{}", types.trim_end()),
+    }
+    Ok(())
+}
+
+/// The `cache.snapshot_path` of the `namespace`'s config.
+fn snapshot_path(config: &Config, namespace: Option<String>) -> Result<String> {
+    let config = config.namespace(namespace.as_deref())?;
+    config.cache.snapshot_path.ok_or_else(|| {
+        "no cache.snapshot_path is configured (AIRTABLE_PROXY_CACHE_SNAPSHOT)".into()
+    })
+}
+
+/// The snapshot at `path`.
+fn read_snapshot(path: &str) -> Result<Snapshot> {
+    let snapshot = std::fs::read_to_string(path).map_err(|source| Error::Snapshot {
+        path: path.to_owned(),
+        source,
+    })?;
+    Ok(serde_json::from_str(&snapshot)?)
+}
+
+/// The cache snapshot at `path`, or none, when it hasn't been saved yet.
+fn read_cache_snapshot(path: &str) -> Result<Snapshot> {
+    match std::fs::read_to_string(path) {
+        Ok(snapshot) => Ok(serde_json::from_str(&snapshot)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Snapshot::default()),
+        Err(source) => Err(Error::Snapshot {
+            path: path.to_owned(),
+            source,
+        }
+        .into()),
+    }
+}
+
+/// Writes the cache snapshot to `path`, or stdout.
+pub fn cache_dump(config: &Config, namespace: Option<String>, path: Option<String>) -> Result<()> {
+    let snapshot = read_cache_snapshot(&snapshot_path(config, namespace)?)?;
+    let snapshot = serde_json::to_string(&snapshot)?;
+    match path {
+        Some(path) => std::fs::write(path, snapshot)?,
+        None => println!("{}", snapshot),
+    }
+    Ok(())
+}
+
+/// Adds the responses in the snapshot at `path` to the cache snapshot, which
+/// replace the ones it has for the same urls. A running proxy overwrites the
+/// snapshot when it exits, so this is for one that's stopped.
+pub fn cache_load(config: &Config, namespace: Option<String>, path: String) -> Result<()> {
+    let snapshot_path = snapshot_path(config, namespace)?;
+    let added = read_snapshot(&path)?;
+    let mut snapshot = read_cache_snapshot(&snapshot_path)?;
+    snapshot
+        .responses
+        .retain(|entry| added.responses.iter().all(|added| added.url != entry.url));
+    let restored = added.responses.len();
+    snapshot.responses.extend(added.responses);
+    std::fs::write(&snapshot_path, serde_json::to_string(&snapshot)?)?;
+    println!("{}", json!({ "restored": restored }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMESPACES: Namespaces = &[
+        (None, &[], &[("invoice", &["list"])]),
+        (Some("timesheets"), &[], &[("entry", &["list"])]),
+        (Some("crm"), &[], &[("contact", &["list"])]),
+    ];
+
+    #[test]
+    fn namespaces_of_modules() {
+        let of = |namespace, module| namespace_of(NAMESPACES, namespace, module).unwrap();
+        assert_eq!(of(None, "invoice"), None);
+        assert_eq!(of(None, "entry"), Some("timesheets"));
+        assert_eq!(of(Some("crm"), "invoice"), Some("crm"));
+        let e = namespace_of(NAMESPACES, Some("hr"), "invoice").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "there's no namespace hr, expected one of: timesheets, crm"
+        );
+    }
+
+    #[test]
+    fn loading_a_missing_snapshot_is_an_error() {
+        let dir = std::env::temp_dir();
+        let saved = dir.join(format!("airtable-proxy-{}.json", std::process::id()));
+        let missing = dir.join("airtable-proxy-missing.json");
+        let mut config = Config::default();
+        config.cache.snapshot_path = Some(saved.to_string_lossy().into_owned());
+        let result = cache_load(&config, None, missing.to_string_lossy().into_owned());
+        match result.unwrap_err().downcast_ref::<Error>() {
+            Some(Error::Snapshot { source, .. }) => {
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound)
+            }
+            e => panic!("{:?}", e),
+        }
+        assert!(!saved.exists());
+    }
+
+    #[test]
+    fn the_cache_snapshot_is_empty_until_its_saved() {
+        let missing = std::env::temp_dir().join("airtable-proxy-missing.json");
+        let snapshot = read_cache_snapshot(&missing.to_string_lossy()).unwrap();
+        assert!(snapshot.responses.is_empty());
+    }
+}
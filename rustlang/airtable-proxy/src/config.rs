@@ -1,39 +1,765 @@
+//! The configuration of the proxy.
+//!
+//! This is read, in order, from
+//!
+//! 1. the defaults here,
+//! 2. the TOML file at `AIRTABLE_PROXY_CONFIG`, if it's set,
+//! 3. and env vars, which override anything in the file.
+//!
+//! See `proxy.example.toml` for the documented schema of the file.
+//!
+//! When the proxy serves several schemas, each is a namespace with its own
+//! `[namespaces.{name}]` section and `{NAME}_AIRTABLE_*` env vars, over
+//! the top-level ones, see `Config::namespace`.
+
+use crate::airtable::Source;
 use crate::error::Error;
+use crate::secret::Secret;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
 
-#[derive(Debug)]
-pub(crate) struct Config {
-    pub key: String,
-    pub base: String,
+/// The name of the base and key that tables use when they don't name one.
+pub const DEFAULT: &str = "default";
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: Server,
+    pub airtable: Airtable,
+    pub cache: Cache,
+    pub http: Http,
+    pub log: Log,
+    /// The overrides for each schema namespace, by name.
+    pub namespaces: HashMap<String, Namespace>,
+    /// The namespace this config is for, when it's been made by `namespace`.
+    #[serde(skip)]
+    name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Server {
+    /// The address to listen on.
+    pub host: String,
+    /// How long to wait for in-flight requests to finish when shutting down.
+    pub shutdown_timeout_seconds: u64,
+    /// Serves `GET /_raw/{module}/{record_id}`, the records as airtable sends them.
+    pub raw_endpoint: bool,
+    /// Serves `GET /docs`, a page that renders the `GET /openapi.json` spec.
+    pub docs_ui: bool,
+    /// Serves `GET /cache/dump` and `POST /cache/load`, which read, and add
+    /// to, what's cached, so any client could serve made up records.
+    pub cache_snapshots: bool,
+    /// How many records deep the linked records of a response can be created,
+    /// counting the one it's for.
+    pub max_depth: usize,
 }
 
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1:3000".to_owned(),
+            shutdown_timeout_seconds: 30,
+            raw_endpoint: false,
+            docs_ui: false,
+            cache_snapshots: false,
+            max_depth: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Airtable {
+    pub api_url: String,
+    /// Airtable API keys by name.
+    pub keys: HashMap<String, Secret>,
+    /// Paths to files with airtable API keys in them, by name.
+    pub key_files: HashMap<String, String>,
+    /// Airtable base (app) IDs by name.
+    pub bases: HashMap<String, String>,
+    pub webhook_secret: Option<Secret>,
+    pub webhook_secret_file: Option<String>,
+}
+
+impl Default for Airtable {
+    fn default() -> Self {
+        Self {
+            api_url: "https://api.airtable.com/v0/".to_owned(),
+            keys: HashMap::new(),
+            key_files: HashMap::new(),
+            bases: HashMap::new(),
+            webhook_secret: None,
+            webhook_secret_file: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cache {
+    pub enabled: bool,
+    /// How long a response is cached for, forever when it isn't set.
+    pub ttl_seconds: Option<u64>,
+    /// A file the cache is loaded from at startup, and saved to at shutdown.
+    pub snapshot_path: Option<String>,
+    /// The size of the biggest attachment that's cached, bigger ones are
+    /// streamed instead of read into memory.
+    pub max_file_bytes: u64,
+    /// The size of all of the cached attachments together, the oldest are
+    /// dropped to make room for new ones.
+    pub max_files_bytes: u64,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            ttl_seconds: None,
+            snapshot_path: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files_bytes: 100 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Http {
+    pub timeout_seconds: u64,
+    /// How many times to retry a request that failed to connect, timed out,
+    /// or got a 429 or 5xx response, at most `MAX_RETRIES`.
+    pub retries: u32,
+    /// The wait before the first retry, doubled for each one after, up to
+    /// 30 seconds.
+    pub retry_backoff_ms: u64,
+}
+
+pub const MAX_RETRIES: u32 = 10;
+
+impl Default for Http {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 30,
+            retries: 0,
+            retry_backoff_ms: 250,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    /// An `env_logger` filter, like `info` or `airtable_proxy=debug`,
+    /// `RUST_LOG` takes precedence over this.
+    pub level: String,
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Self {
+            level: "info".to_owned(),
+        }
+    }
+}
+
+/// What a namespace changes from the top-level config.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Namespace {
+    /// The path its routes are mounted under, the namespace's name by default.
+    pub prefix: Option<String>,
+    pub airtable: NamespaceAirtable,
+    /// Replaces the top-level `[cache]`, it's always a separate cache.
+    pub cache: Option<Cache>,
+}
+
+/// The credentials of a namespace, added to (or replacing) the top-level ones.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NamespaceAirtable {
+    pub keys: HashMap<String, Secret>,
+    pub key_files: HashMap<String, String>,
+    pub bases: HashMap<String, String>,
+    pub webhook_secret: Option<Secret>,
+    pub webhook_secret_file: Option<String>,
+}
+
+/// The `path` of a file, with the `namespace` before its extension, so
+/// `cache.json` is `cache.timesheets.json`.
+fn namespaced_path(path: &str, namespace: &str) -> String {
+    let path = std::path::Path::new(path);
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(namespace);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// One thing wrong with the configuration.
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("could not read {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("could not parse {path}: {source}")]
+    Parse {
+        path: String,
+        #[source]
+        source: toml::de::Error,
+    },
+    #[error("{name} is not valid, {message}")]
+    Env {
+        name: &'static str,
+        message: String,
+    },
+    #[error("missing {key}, set it in the config file or with {env}")]
+    Missing { key: String, env: String },
+    #[error("{key} is not valid, {message}")]
+    Invalid { key: String, message: String },
+}
+
+/// All the problems we found with the configuration, reported together.
 #[derive(Debug)]
-pub struct EnvKeys([ &'static str; 2 ]);
+pub struct ConfigErrors(pub Vec<ConfigError>);
 
-impl std::fmt::Display for EnvKeys {
+impl std::fmt::Display for ConfigErrors {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
-        write!(f, "{:?}", self.0)
+        for (idx, problem) in self.0.iter().enumerate() {
+            if idx > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the env var `name`, if it's set.
+fn env_var<T: std::str::FromStr>(name: &'static str, problems: &mut Vec<ConfigError>) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = std::env::var(name).ok()?;
+    match value.parse() {
+        Ok(value) => Some(value),
+        Err(e) => {
+            problems.push(ConfigError::Env {
+                name,
+                message: e.to_string(),
+            });
+            None
+        }
     }
 }
 
 impl Config {
-    const KEYS: EnvKeys = EnvKeys(["AIRTABLE_KEY", "AIRTABLE_APP"]);
+    const FILE: &'static str = "AIRTABLE_PROXY_CONFIG";
+    const KEY: &'static str = "AIRTABLE_KEY";
+    const BASE: &'static str = "AIRTABLE_APP";
+    const WEBHOOK_SECRET: &'static str = "AIRTABLE_WEBHOOK_SECRET";
+    const WEBHOOK_SECRET_FILE: &'static str = "AIRTABLE_WEBHOOK_SECRET_FILE";
+    const API_URL: &'static str = "AIRTABLE_API_URL";
+    const HOST: &'static str = "HOST";
+    const CACHE_ENABLED: &'static str = "AIRTABLE_PROXY_CACHE";
+    const CACHE_TTL: &'static str = "AIRTABLE_PROXY_CACHE_TTL";
+    const CACHE_SNAPSHOT: &'static str = "AIRTABLE_PROXY_CACHE_SNAPSHOT";
+    const CACHE_MAX_FILE_BYTES: &'static str = "AIRTABLE_PROXY_CACHE_MAX_FILE_BYTES";
+    const CACHE_MAX_FILES_BYTES: &'static str = "AIRTABLE_PROXY_CACHE_MAX_FILES_BYTES";
+    const SHUTDOWN_TIMEOUT: &'static str = "AIRTABLE_PROXY_SHUTDOWN_TIMEOUT";
+    const RAW_ENDPOINT: &'static str = "AIRTABLE_PROXY_RAW_ENDPOINT";
+    const DOCS_UI: &'static str = "AIRTABLE_PROXY_DOCS_UI";
+    const MAX_DEPTH: &'static str = "AIRTABLE_PROXY_MAX_DEPTH";
+    const CACHE_SNAPSHOTS: &'static str = "AIRTABLE_PROXY_CACHE_SNAPSHOTS";
+    const TIMEOUT: &'static str = "AIRTABLE_PROXY_TIMEOUT";
+    const RETRIES: &'static str = "AIRTABLE_PROXY_RETRIES";
+    const LOG: &'static str = "AIRTABLE_PROXY_LOG";
+
+    /// Loads the config file (if there is one), applies env overrides,
+    /// and validates the result.
+    pub fn load() -> Result<Self, Error> {
+        let mut problems = vec![];
+        let mut config = match std::env::var(Self::FILE) {
+            Ok(path) => Self::from_file(&path).unwrap_or_else(|e| {
+                problems.push(e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        };
+        config.apply_env(&mut problems);
+        config.read_secret_files(&mut problems);
+        config.validate(&mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::Config {
+                problems: ConfigErrors(problems),
+            })
+        }
+    }
+
+    /// The config for the namespace `name`, which is this one, with the
+    /// credentials and cache from its `[namespaces.{name}]` section and
+    /// `{NAME}_AIRTABLE_KEY`, `{NAME}_AIRTABLE_APP`, etc. env vars.
+    ///
+    /// Without a `name`, it's the config for a schema mounted at the root.
+    pub fn namespace(&self, name: Option<&str>) -> Result<Self, Error> {
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(self.clone()),
+        };
+        let mut config = Self {
+            namespaces: HashMap::new(),
+            name: Some(name.to_owned()),
+            ..self.clone()
+        };
+        if let Some(namespace) = self.namespaces.get(name) {
+            let airtable = namespace.airtable.clone();
+            for (key, secret) in airtable.keys {
+                config.airtable.key_files.remove(&key);
+                config.airtable.keys.insert(key, secret);
+            }
+            config.airtable.key_files.extend(airtable.key_files);
+            config.airtable.bases.extend(airtable.bases);
+            if airtable.webhook_secret.is_some() {
+                config.airtable.webhook_secret_file = None;
+                config.airtable.webhook_secret = airtable.webhook_secret;
+            }
+            if airtable.webhook_secret_file.is_some() {
+                config.airtable.webhook_secret_file = airtable.webhook_secret_file;
+            }
+            if let Some(cache) = &namespace.cache {
+                config.cache = cache.clone();
+            }
+        }
+        // each namespace's cache is separate, so is its snapshot, unless it
+        // sets its own, it's the top-level one with its name added
+        let own_cache = self
+            .namespaces
+            .get(name)
+            .is_some_and(|namespace| namespace.cache.is_some());
+        if !own_cache {
+            config.cache.snapshot_path = config
+                .cache
+                .snapshot_path
+                .as_deref()
+                .map(|path| namespaced_path(path, name));
+        }
+        config.apply_credentials_env(&config.env_prefix());
+
+        let mut problems = vec![];
+        config.read_secret_files(&mut problems);
+        config.validate(&mut problems);
+        if problems.is_empty() {
+            Ok(config)
+        } else {
+            Err(Error::Config {
+                problems: ConfigErrors(problems),
+            })
+        }
+    }
+
+    /// The path the namespace `name` is mounted under, without slashes.
+    pub fn prefix<'a>(&'a self, name: &'a str) -> &'a str {
+        self.namespaces
+            .get(name)
+            .and_then(|namespace| namespace.prefix.as_deref())
+            .unwrap_or(name)
+            .trim_matches('/')
+    }
+
+    /// The prefix of the env vars for the credentials of this config's namespace.
+    fn env_prefix(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{}_", name.to_uppercase()),
+            None => String::new(),
+        }
+    }
+
+    /// The name of the namespace this config is for, if it's for one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
 
-    pub(crate) fn from_env() -> Result<Self, crate::error::Error> {
-        match Self::KEYS {
-            EnvKeys([key, base]) => match (std::env::var(key), std::env::var(base)) {
-                (Ok(key), Ok(base)) => Ok(Self { key, base }),
-                _ => Err(Error::MissingEnvConfig {
-                    names: Self::KEYS
-                }),
-            },
+    fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_owned(),
+            source,
+        })?;
+        toml::from_str(&contents).map_err(|source| ConfigError::Parse {
+            path: path.to_owned(),
+            source,
+        })
+    }
+
+    /// Finds the named values in env vars like `AIRTABLE_KEY_CLIENTS`, which is
+    /// named `clients`, the unsuffixed one is the `DEFAULT`.
+    ///
+    /// Vars ending in `_FILE` are the paths to files with the value in them,
+    /// so `AIRTABLE_KEY_FILE` and `AIRTABLE_KEY_CLIENTS_FILE` are the same ones,
+    /// from files. These are returned as `(name, value, is_file)`.
+    fn named_from_env(var: &str) -> Vec<(String, String, bool)> {
+        let prefix = format!("{}_", var);
+        std::env::vars()
+            .filter_map(|(name, value)| {
+                let rest = if name == var {
+                    ""
+                } else {
+                    name.strip_prefix(&prefix)?
+                };
+                let (rest, is_file) = match rest {
+                    "FILE" => ("", true),
+                    _ => match rest.strip_suffix("_FILE") {
+                        Some(rest) => (rest, true),
+                        None => (rest, false),
+                    },
+                };
+                let name = match rest {
+                    "" => DEFAULT.to_owned(),
+                    _ => rest.to_lowercase(),
+                };
+                Some((name, value, is_file))
+            })
+            .collect()
+    }
+
+    /// Applies the env vars for keys, bases, and the webhook secret, which
+    /// are prefixed with `{NAME}_` for a namespace.
+    fn apply_credentials_env(&mut self, prefix: &str) {
+        let var = |name: &str| format!("{}{}", prefix, name);
+        for (name, value, is_file) in Self::named_from_env(&var(Self::KEY)) {
+            if is_file {
+                self.airtable.keys.remove(&name);
+                self.airtable.key_files.insert(name, value);
+            } else {
+                self.airtable.key_files.remove(&name);
+                self.airtable.keys.insert(name, Secret::new(value));
+            }
+        }
+        for (name, value, is_file) in Self::named_from_env(&var(Self::BASE)) {
+            if !is_file {
+                self.airtable.bases.insert(name, value);
+            }
+        }
+        if let Ok(secret) = std::env::var(var(Self::WEBHOOK_SECRET)) {
+            self.airtable.webhook_secret_file = None;
+            self.airtable.webhook_secret = Some(Secret::new(secret));
+        }
+        if let Ok(path) = std::env::var(var(Self::WEBHOOK_SECRET_FILE)) {
+            self.airtable.webhook_secret_file = Some(path);
         }
     }
 
-    pub(crate) fn table_url(&self, table: &str) -> String {
+    fn apply_env(&mut self, problems: &mut Vec<ConfigError>) {
+        self.apply_credentials_env("");
+        if let Ok(api_url) = std::env::var(Self::API_URL) {
+            self.airtable.api_url = api_url;
+        }
+        if let Ok(host) = std::env::var(Self::HOST) {
+            self.server.host = host;
+        }
+        if let Some(timeout) = env_var(Self::SHUTDOWN_TIMEOUT, problems) {
+            self.server.shutdown_timeout_seconds = timeout;
+        }
+        if let Some(raw_endpoint) = env_var(Self::RAW_ENDPOINT, problems) {
+            self.server.raw_endpoint = raw_endpoint;
+        }
+        if let Some(docs_ui) = env_var(Self::DOCS_UI, problems) {
+            self.server.docs_ui = docs_ui;
+        }
+        if let Some(cache_snapshots) = env_var(Self::CACHE_SNAPSHOTS, problems) {
+            self.server.cache_snapshots = cache_snapshots;
+        }
+        if let Some(max_depth) = env_var(Self::MAX_DEPTH, problems) {
+            self.server.max_depth = max_depth;
+        }
+        if let Some(enabled) = env_var(Self::CACHE_ENABLED, problems) {
+            self.cache.enabled = enabled;
+        }
+        if let Some(ttl) = env_var(Self::CACHE_TTL, problems) {
+            self.cache.ttl_seconds = Some(ttl);
+        }
+        if let Ok(path) = std::env::var(Self::CACHE_SNAPSHOT) {
+            self.cache.snapshot_path = Some(path);
+        }
+        if let Some(max_file_bytes) = env_var(Self::CACHE_MAX_FILE_BYTES, problems) {
+            self.cache.max_file_bytes = max_file_bytes;
+        }
+        if let Some(max_files_bytes) = env_var(Self::CACHE_MAX_FILES_BYTES, problems) {
+            self.cache.max_files_bytes = max_files_bytes;
+        }
+        if let Some(timeout) = env_var(Self::TIMEOUT, problems) {
+            self.http.timeout_seconds = timeout;
+        }
+        if let Some(retries) = env_var(Self::RETRIES, problems) {
+            self.http.retries = retries;
+        }
+        if let Ok(level) = std::env::var(Self::LOG) {
+            self.log.level = level;
+        }
+    }
+
+    /// Reads the secrets that are configured as paths to files.
+    fn read_secret_files(&mut self, problems: &mut Vec<ConfigError>) {
+        let read = |path: &str, problems: &mut Vec<ConfigError>| {
+            Secret::from_file(path)
+                .map_err(|source| {
+                    problems.push(ConfigError::Read {
+                        path: path.to_owned(),
+                        source,
+                    })
+                })
+                .ok()
+        };
+        for (name, path) in &self.airtable.key_files {
+            if let Some(key) = read(path, problems) {
+                self.airtable.keys.insert(name.clone(), key);
+            }
+        }
+        if let Some(path) = &self.airtable.webhook_secret_file {
+            self.airtable.webhook_secret = read(path, problems);
+        }
+    }
+
+    /// Validates everything but the keys and bases, since which of those are
+    /// needed depends on the schema, see `validate_sources`.
+    fn validate(&self, problems: &mut Vec<ConfigError>) {
+        if let Err(e) = url::Url::parse(&self.airtable.api_url) {
+            problems.push(ConfigError::Invalid {
+                key: "airtable.api_url".to_owned(),
+                message: e.to_string(),
+            });
+        }
+        if let Some(secret) = &self.airtable.webhook_secret {
+            if base64::decode(secret.expose()).is_err() {
+                problems.push(ConfigError::Invalid {
+                    key: "airtable.webhook_secret".to_owned(),
+                    message: "it must be base64".to_owned(),
+                });
+            }
+        }
+        if let Err(e) = self.server.host.parse::<std::net::SocketAddr>() {
+            problems.push(ConfigError::Invalid {
+                key: "server.host".to_owned(),
+                message: e.to_string(),
+            });
+        }
+        if self.server.max_depth == 0 {
+            problems.push(ConfigError::Invalid {
+                key: "server.max_depth".to_owned(),
+                message: "it must be more than 0".to_owned(),
+            });
+        }
+        if self.http.timeout_seconds == 0 {
+            problems.push(ConfigError::Invalid {
+                key: "http.timeout_seconds".to_owned(),
+                message: "it must be more than 0".to_owned(),
+            });
+        }
+        if self.http.retries > MAX_RETRIES {
+            problems.push(ConfigError::Invalid {
+                key: "http.retries".to_owned(),
+                message: format!("it must be at most {}", MAX_RETRIES),
+            });
+        }
+    }
+
+    /// Checks that every base and key the tables in a schema name are configured.
+    pub fn validate_sources(&self, sources: crate::gen_schema::Sources) -> Result<(), Error> {
+        let mut problems = vec![];
+        for (module, source) in sources {
+            if !self.airtable.bases.contains_key(source.base) {
+                problems.push(ConfigError::Missing {
+                    key: format!("airtable.bases.{} (for {})", source.base, module),
+                    env: Self::named_env(&self.env_prefix(), Self::BASE, source.base),
+                });
+            }
+            if !self.airtable.keys.contains_key(source.key) {
+                problems.push(ConfigError::Missing {
+                    key: format!("airtable.keys.{} (for {})", source.key, module),
+                    env: Self::named_env(&self.env_prefix(), Self::KEY, source.key),
+                });
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Config {
+                problems: ConfigErrors(problems),
+            })
+        }
+    }
+
+    /// The env var for the `name`d value of `var`, like `AIRTABLE_KEY_CLIENTS`.
+    fn named_env(prefix: &str, var: &str, name: &str) -> String {
+        match name {
+            DEFAULT => format!("{}{}", prefix, var),
+            _ => format!("{}{}_{}", prefix, var, name.to_uppercase()),
+        }
+    }
+
+    /// The filters for the logger, `RUST_LOG` wins if it's set.
+    pub fn log_filters(&self) -> String {
+        std::env::var("RUST_LOG").unwrap_or_else(|_| self.log.level.clone())
+    }
+
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.server.shutdown_timeout_seconds)
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        Duration::from_secs(self.http.timeout_seconds)
+    }
+
+    pub(crate) fn ttl(&self) -> Option<Duration> {
+        self.cache.ttl_seconds.map(Duration::from_secs)
+    }
+
+    /// The API root, always with a trailing `/`.
+    fn api_url(&self) -> String {
+        format!("{}/", self.airtable.api_url.trim_end_matches('/'))
+    }
+
+    /// The base ID for the `name`d base.
+    pub(crate) fn base(&self, name: &'static str) -> Result<&str, Error> {
+        match self.airtable.bases.get(name) {
+            Some(base) => Ok(base),
+            None => Err(Error::UnknownConfig { kind: "base", name }),
+        }
+    }
+
+    /// The API key for the `name`d key.
+    pub(crate) fn key(&self, name: &'static str) -> Result<&str, Error> {
+        match self.airtable.keys.get(name) {
+            Some(key) => Ok(key.expose()),
+            None => Err(Error::UnknownConfig { kind: "key", name }),
+        }
+    }
+
+    /// The name of the base with the ID `base`.
+    pub(crate) fn base_name(&self, base: &str) -> Option<&str> {
+        self.airtable
+            .bases
+            .iter()
+            .find(|(_, id)| *id == base)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub(crate) fn table_url(&self, source: Source) -> Result<String, Error> {
+        Ok(format!(
+            "{api}{base}/{table}",
+            api = self.api_url(),
+            base = self.base(source.base)?,
+            table = source.table
+        ))
+    }
+
+    pub(crate) fn webhook_payloads_url(&self, base: &str, webhook: &str) -> String {
         format!(
-            "https://api.airtable.com/v0/{base}/{table}",
-            base = self.base,
-            table = table
+            "{api}bases/{base}/webhooks/{webhook}/payloads",
+            api = self.api_url(),
+            base = base,
+            webhook = webhook
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The problems `validate` finds with the `config`.
+    fn problems(config: &Config) -> Vec<String> {
+        let mut problems = vec![];
+        config.validate(&mut problems);
+        problems.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn the_default_is_valid() {
+        assert!(problems(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn validate_finds_every_problem() {
+        let mut config = Config::default();
+        config.airtable.api_url = "not a url".to_owned();
+        config.airtable.webhook_secret = Some(Secret::new("not base64!".to_owned()));
+        config.server.host = "localhost".to_owned();
+        config.server.max_depth = 0;
+        config.http.timeout_seconds = 0;
+        config.http.retries = MAX_RETRIES + 1;
+        let problems = problems(&config);
+        let keys = [
+            "airtable.api_url",
+            "airtable.webhook_secret",
+            "server.host",
+            "server.max_depth",
+            "http.timeout_seconds",
+            "http.retries",
+        ];
+        assert_eq!(problems.len(), keys.len(), "{:?}", problems);
+        for (problem, key) in problems.iter().zip(&keys) {
+            assert!(problem.starts_with(key), "{} isn't about {}", problem, key);
+        }
+    }
+
+    #[test]
+    fn namespaced_paths() {
+        assert_eq!(
+            namespaced_path("/var/lib/cache.json", "timesheets"),
+            "/var/lib/cache.timesheets.json"
+        );
+        assert_eq!(namespaced_path("cache", "timesheets"), "cache.timesheets");
+        assert_eq!(
+            namespaced_path("data/cache.tar.gz", "a"),
+            "data/cache.tar.a.gz"
+        );
+    }
+
+    #[test]
+    fn env_overrides() {
+        std::env::set_var(Config::CACHE_TTL, "30");
+        std::env::set_var(Config::MAX_DEPTH, "deep");
+        let mut config = Config::default();
+        let mut problems = vec![];
+        config.apply_env(&mut problems);
+        std::env::remove_var(Config::CACHE_TTL);
+        std::env::remove_var(Config::MAX_DEPTH);
+
+        assert_eq!(config.cache.ttl_seconds, Some(30));
+        assert_eq!(config.server.max_depth, Config::default().server.max_depth);
+        match problems.as_slice() {
+            [ConfigError::Env { name, .. }] => assert_eq!(*name, Config::MAX_DEPTH),
+            problems => panic!("the problems were {:?}", problems),
+        }
+    }
+
+    #[test]
+    fn namespace_env_overrides() {
+        std::env::set_var("ENVTEST_AIRTABLE_KEY", "keyDEFAULT");
+        std::env::set_var("ENVTEST_AIRTABLE_APP_CLIENTS", "appCLIENTS");
+        let mut config = Config::default();
+        config.cache.snapshot_path = Some("cache.json".to_owned());
+        let namespace = config.namespace(Some("envtest"));
+        std::env::remove_var("ENVTEST_AIRTABLE_KEY");
+        std::env::remove_var("ENVTEST_AIRTABLE_APP_CLIENTS");
+
+        let namespace = namespace.unwrap();
+        assert_eq!(namespace.name(), Some("envtest"));
+        assert_eq!(namespace.airtable.keys[DEFAULT].expose(), "keyDEFAULT");
+        assert_eq!(namespace.airtable.bases["clients"], "appCLIENTS");
+        assert_eq!(
+            namespace.cache.snapshot_path.as_deref(),
+            Some("cache.envtest.json")
+        );
+        assert!(config.airtable.keys.is_empty());
+    }
+}
@@ -20,6 +20,28 @@ pub fn wrap_ctx(ctx: airtable::FetchCtx) -> Ctx {
     Arc::new(Mutex::new(ctx))
 }
 
+/// Reloads the secrets of the `FetchCtx`s every time the process gets a SIGHUP,
+/// so keys can be rotated without a restart.
+#[cfg(unix)]
+pub async fn reload_secrets_on_hangup(ctxs: Vec<Ctx>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            crate::warn!("Could not listen for SIGHUP, secrets won't reload. {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        crate::info!("Got SIGHUP, reloading secrets.");
+        for ctx in &ctxs {
+            if let Err(e) = ctx.lock().await.reload_secrets() {
+                crate::warn!("Could not reload secrets, keeping the old ones. {}", e);
+            }
+        }
+    }
+}
+
 /// This function creates a filter that adds a context
 /// to all the endpoints that need it.
 pub fn with_ctx(ctx: Ctx) -> impl Filter<Extract = (Ctx,), Error = Infallible> + Clone {
@@ -29,6 +51,10 @@ pub fn with_ctx(ctx: Ctx) -> impl Filter<Extract = (Ctx,), Error = Infallible> +
 pub mod ctx_cache {
 
     use super::*;
+    use crate::network::cache::Snapshot;
+
+    /// The biggest snapshot `POST /cache/load` takes.
+    const MAX_SNAPSHOT_BYTES: u64 = 32 * 1024 * 1024;
 
     /// Shows the stats for the cache of the `FetchCtx`.
     async fn show(ctx: Ctx) -> Result<impl Reply, Rejection> {
@@ -49,6 +75,26 @@ pub mod ctx_cache {
         show(ctx).await
     }
 
+    /// Responds with a `Snapshot` of the cached responses, when the config
+    /// serves it.
+    async fn dump(ctx: Ctx) -> Result<impl Reply, Rejection> {
+        let ctx = ctx.lock().await;
+        if !ctx.cache_snapshots() {
+            return Err(warp::reject::not_found());
+        }
+        Ok(warp::reply::json(&ctx.cache.snapshot()))
+    }
+
+    /// Adds the responses in a `Snapshot` to the cache, when the config serves it.
+    async fn load(snapshot: Snapshot, ctx: Ctx) -> Result<impl Reply, Rejection> {
+        let mut ctx = ctx.lock().await;
+        if !ctx.cache_snapshots() {
+            return Err(warp::reject::not_found());
+        }
+        let restored = ctx.cache.restore(snapshot);
+        Ok(warp::reply::json(&json!({ "restored": restored })))
+    }
+
     pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
         // GET /cache
         let cache = warp::path("cache").and(warp::get());
@@ -57,8 +103,80 @@ pub mod ctx_cache {
         let show_stats = warp::path("stats")
             .and(with_ctx(ctx.clone()))
             .and_then(show);
-        let clear_stats = warp::path("clear").and(with_ctx(ctx)).and_then(clear);
+        let clear_stats = warp::path("clear")
+            .and(with_ctx(ctx.clone()))
+            .and_then(clear);
+        let dump_cache = warp::path("dump").and(with_ctx(ctx.clone())).and_then(dump);
+
+        // POST /cache/load
+        let load_cache = warp::path("cache")
+            .and(warp::path("load"))
+            .and(warp::post())
+            .and(warp::body::content_length_limit(MAX_SNAPSHOT_BYTES))
+            .and(warp::body::json())
+            .and(with_ctx(ctx))
+            .and_then(load);
+
+        cache
+            .and(show_stats.or(clear_stats).or(dump_cache))
+            .or(load_cache)
+    }
+}
+
+pub mod ctx_attachments {
+
+    use super::*;
+    use crate::gen_schema::Sources;
+    use crate::network::cache::Download;
+    use percent_encoding::percent_decode_str;
+    use warp::hyper::Body;
+
+    /// Responds with the bytes of the `n`th attachment in `field` for the `record`,
+    /// the ones too big to cache are streamed after the `ctx` is unlocked.
+    async fn show(
+        module: String,
+        record: String,
+        field: String,
+        n: usize,
+        tables: Sources,
+        ctx: Ctx,
+    ) -> Result<warp::reply::Response, Rejection> {
+        let source = match tables.iter().find(|(name, _)| *name == module) {
+            Some((_, source)) => *source,
+            None => return Err(warp::reject::not_found()),
+        };
+        let field = percent_decode_str(&field).decode_utf8_lossy();
+        let mut ctx = ctx.lock().await;
+        match ctx.fetch_attachment(source, &record, &field, n).await {
+            Ok(download) => {
+                let (content_type, body) = match download {
+                    Download::File(file) => (file.content_type, Body::from(file.bytes)),
+                    Download::Stream { content_type, body } => (content_type, body),
+                };
+                Ok(warp::reply::with_header(
+                    warp::http::Response::new(body),
+                    "content-type",
+                    content_type,
+                )
+                .into_response())
+            }
+            Err(e) => crate::gen_schema::respond(Err(e)).map(Reply::into_response),
+        }
+    }
 
-        cache.and(show_stats.or(clear_stats))
+    pub fn route(
+        ctx: Ctx,
+        tables: Sources,
+    ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+        // GET /attachments/{module}/{record}/{field}/{n}
+        warp::path("attachments")
+            .and(warp::path::param::<String>())
+            .and(warp::path::param::<String>())
+            .and(warp::path::param::<String>())
+            .and(warp::path::param::<usize>())
+            .and(warp::get())
+            .and(warp::any().map(move || tables))
+            .and(with_ctx(ctx))
+            .and_then(show)
     }
 }
@@ -2,17 +2,45 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Error creating record {table}")]
+    #[error("Error creating record {id} in {table}, field {field}")]
     Create {
         table: &'static str,
+        id: String,
+        field: &'static str,
         #[source] source: Box<Error>,
     },
-    #[error("Missing required environment variables: {names}")]
-    MissingEnvConfig{
-        names: crate::config::EnvKeys,
+    #[error("Linked records link back to one being created: {}", path.join(" > "))]
+    Cycle {
+        path: Vec<String>,
+    },
+    #[error("Invalid configuration: {problems}")]
+    Config {
+        problems: crate::config::ConfigErrors,
+    },
+    #[error("Invalid argument {arg} for endpoint {endpoint}")]
+    InvalidArgument {
+        endpoint: &'static str,
+        arg: String,
+    },
+    #[error("The signed url of an attachment has expired, url={url}")]
+    Expired { url: String },
+    #[error("Invalid {param}={value}, expected {expected}")]
+    InvalidQuery {
+        param: String,
+        value: String,
+        expected: String,
+    },
+    #[error("No value in the column {column}, airtable leaves out empty cells")]
+    MissingValue {
+        column: &'static str,
     },
     #[error(transparent)]
     Req(reqwest::Error),
+    #[error("No record {key} in {table}")]
+    NotFound {
+        table: &'static str,
+        key: String,
+    },
     #[error("{message} for table={table}")]
     RequestParams {
         table: &'static str,
@@ -22,12 +50,65 @@ pub enum Error {
     Response { status: String, url: String },
     #[error(transparent)]
     SerdeTransform(serde_json::error::Error),
+    #[error("Could not read or write the cache snapshot at {path}")]
+    Snapshot {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("Linked records nested deeper than {max_depth}: {}", path.join(" > "))]
+    TooDeep {
+        max_depth: usize,
+        path: Vec<String>,
+    },
     #[error("Error during transform function, {message}")]
     Transform {
         message: &'static str,
     },
+    #[error("No {kind} named {name} is configured")]
+    UnknownConfig {
+        kind: &'static str,
+        name: &'static str,
+    },
+    #[error("No endpoint {endpoint} in module {module}")]
+    UnknownEndpoint {
+        module: String,
+        endpoint: String,
+    },
+    #[error("No field {field} in {table}, expected one of: {}", valid.join(", "))]
+    UnknownField {
+        table: &'static str,
+        field: String,
+        valid: &'static [&'static str],
+    },
     #[error(transparent)]
     UrlParser(url::ParseError),
+    #[error("Invalid webhook notification, {message}")]
+    Webhook {
+        message: &'static str,
+    },
+}
+
+impl Error {
+    /// The message of the error, and of each of the ones under it.
+    pub fn chain(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(e) = source {
+            message = format!("{}: {}", message, e);
+            source = e.source();
+        }
+        message
+    }
+
+    /// The error under the `Create`s it's wrapped in, for the field
+    /// of each of the records it happened in.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Create { source, .. } => source.root(),
+            e => e,
+        }
+    }
 }
 
 impl warp::reject::Reject for Error { }
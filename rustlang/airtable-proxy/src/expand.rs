@@ -0,0 +1,336 @@
+//! Which linked records `create_one` hydrates, from the `?expand=` of a
+//! request, like `?expand=client,items.rate.unit`, and which of the fields are
+//! in the response, from its `?fields=`, like `?fields=number,client.company`.
+//!
+//! A field marked `link;` is a `Link`, it's the IDs of the linked records,
+//! unless it's expanded, or some of its fields are asked for, then its `exec`
+//! is run to make it `Link::Expanded`. When there's a `?fields=`, the linked
+//! records that aren't in it aren't fetched at all.
+
+use crate::error::Error;
+use crate::keys::{Keys, Rename};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// A tree of field names, each with the fields under it, in the records it
+/// links to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Paths(BTreeMap<String, Paths>);
+
+impl Paths {
+    /// Parses a comma separated list of dotted paths, so `client,items.rate.unit`
+    /// is `client`, `items`, the `rate` of each item, and the `unit` of those.
+    pub fn parse(paths: &str) -> Self {
+        let mut tree = Self::default();
+        for path in paths
+            .split(',')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+        {
+            path.split('.')
+                .map(str::trim)
+                .fold(&mut tree, |tree, field| {
+                    tree.0.entry(field.to_owned()).or_default()
+                });
+        }
+        tree
+    }
+
+    /// The paths under `field`, if it's in these.
+    pub fn field(&self, field: &str) -> Option<&Paths> {
+        self.0.get(field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Errors with the first of these fields that isn't in the `valid` ones,
+    /// the names of the fields of the `table`'s `Mapped`.
+    pub fn check(&self, table: &'static str, valid: &'static [&'static str]) -> Result<(), Error> {
+        match self.0.keys().find(|field| !valid.contains(&field.as_str())) {
+            Some(field) => Err(Error::UnknownField {
+                table,
+                field: field.clone(),
+                valid,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the keys that aren't in these paths from the objects in the
+    /// `value`, going into the ones with paths under them.
+    pub fn select(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                object.retain(|key, _| self.0.contains_key(key));
+                for (key, value) in object.iter_mut() {
+                    match self.field(key) {
+                        Some(paths) if !paths.is_empty() => paths.select(value),
+                        _ => {}
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.select(value)),
+            _ => {}
+        }
+    }
+}
+
+/// What's expanded, and which fields are asked for, under the record that's
+/// being created, `FetchCtx` has the one for the request it's handling.
+#[derive(Debug, Clone, Default)]
+pub struct Scope {
+    pub expand: Paths,
+    /// All of them, without a `?fields=`.
+    pub fields: Option<Paths>,
+    /// The endpoint's, without a `?keys=`.
+    pub keys: Option<Keys>,
+    /// The records being created, from the one the request is for, down to
+    /// the one this is the scope of.
+    path: Vec<Hop>,
+}
+
+/// A record on the path of a `Scope`, with what was expanded under it.
+#[derive(Debug, Clone, PartialEq)]
+struct Hop {
+    table: &'static str,
+    id: String,
+    expand: Paths,
+    fields: Option<Paths>,
+}
+
+impl Scope {
+    /// The scope to hydrate the records linked by `field` in, or `None` when
+    /// they're left as IDs.
+    pub fn link(&self, field: &str) -> Option<Scope> {
+        let fields = match &self.fields {
+            None => None,
+            Some(fields) => match fields.field(field) {
+                None => return None,
+                Some(paths) if paths.is_empty() => None,
+                Some(paths) => Some(paths.clone()),
+            },
+        };
+        // asking for fields of the linked records expands them
+        let expand = match (self.expand.field(field), &fields) {
+            (Some(expand), _) => expand.clone(),
+            (None, Some(_)) => Paths::default(),
+            (None, None) => return None,
+        };
+        Some(Scope {
+            expand,
+            fields,
+            keys: self.keys,
+            path: self.path.clone(),
+        })
+    }
+
+    /// Adds the record `id` of the `table` to the path, until `leave`. Errors
+    /// when it's already on it, in this same scope, since it would link back to
+    /// itself forever, or when the path would be longer than `max_depth`.
+    pub fn enter(&mut self, table: &'static str, id: &str, max_depth: usize) -> Result<(), Error> {
+        let hop = Hop {
+            table,
+            id: id.to_owned(),
+            expand: self.expand.clone(),
+            fields: self.fields.clone(),
+        };
+        let cycle = self.path.contains(&hop);
+        self.path.push(hop);
+        let error = match (cycle, self.path.len() > max_depth) {
+            (true, _) => Error::Cycle { path: self.path() },
+            (false, true) => Error::TooDeep {
+                max_depth,
+                path: self.path(),
+            },
+            (false, false) => return Ok(()),
+        };
+        self.path.pop();
+        Err(error)
+    }
+
+    /// Takes the last record `enter` added off the path.
+    pub fn leave(&mut self) {
+        self.path.pop();
+    }
+
+    /// Each record on the path, like `Invoices recA1b2`.
+    pub fn path(&self) -> Vec<String> {
+        self.path
+            .iter()
+            .map(|hop| format!("{} {}", hop.table, hop.id))
+            .collect()
+    }
+
+    /// Checks the asked for fields are some of the `table`'s, see `Paths::check`.
+    pub fn check(&self, table: &'static str, valid: &'static [&'static str]) -> Result<(), Error> {
+        match &self.fields {
+            Some(fields) => fields.check(table, valid),
+            None => Ok(()),
+        }
+    }
+
+    /// The response with only the asked for fields.
+    pub fn select(&self, mut value: Value) -> Value {
+        if let Some(fields) = &self.fields {
+            fields.select(&mut value);
+        }
+        value
+    }
+
+    /// The response, a `T`, with only the asked for fields, and their keys,
+    /// the endpoint's `keys` when the request doesn't ask for any.
+    pub fn render<T: Rename>(&self, value: Value, keys: Keys) -> Value {
+        let mut value = self.select(value);
+        self.keys.unwrap_or(keys).rename::<T>(&mut value);
+        value
+    }
+}
+
+/// A linked record field, the IDs of the records it links to, or, when it's
+/// expanded, those records, made by the field's `exec`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Link<T> {
+    Ids(Vec<String>),
+    Expanded(T),
+}
+
+impl<T> Link<T> {
+    /// The linked records, if they were expanded.
+    pub fn expanded(&self) -> Option<&T> {
+        match self {
+            Link::Expanded(value) => Some(value),
+            Link::Ids(_) => None,
+        }
+    }
+}
+
+/// The query parameters of the generated endpoints.
+#[derive(Debug, Default, Deserialize)]
+pub struct Query {
+    pub expand: Option<String>,
+    pub fields: Option<String>,
+    pub keys: Option<String>,
+}
+
+impl Query {
+    /// The scope of the request, or `InvalidQuery` for keys that aren't `Keys`.
+    pub fn scope(&self) -> Result<Scope, Error> {
+        Ok(Scope {
+            expand: self.expand.as_deref().map(Paths::parse).unwrap_or_default(),
+            fields: self.fields.as_deref().map(Paths::parse),
+            keys: self.keys.as_deref().map(str::parse).transpose()?,
+            path: vec![],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const FIELDS: &[&str] = &["number", "client", "items"];
+
+    #[test]
+    fn paths_are_a_tree() {
+        let paths = Paths::parse(" client , items.rate.unit,items.quantity,,");
+        assert!(paths.field("client").unwrap().is_empty());
+        let items = paths.field("items").unwrap();
+        assert!(items.field("quantity").unwrap().is_empty());
+        assert!(items
+            .field("rate")
+            .and_then(|rate| rate.field("unit"))
+            .is_some());
+        assert!(paths.field("rate").is_none());
+        assert!(Paths::parse("").is_empty());
+    }
+
+    #[test]
+    fn check_finds_unknown_fields() {
+        assert!(Paths::parse("number,client.company")
+            .check("Invoice", FIELDS)
+            .is_ok());
+        match Paths::parse("number,total").check("Invoice", FIELDS) {
+            Err(Error::UnknownField { table, field, .. }) => {
+                assert_eq!(table, "Invoice");
+                assert_eq!(field, "total");
+            }
+            result => panic!("total was {:?}", result),
+        }
+    }
+
+    #[test]
+    fn select_keeps_the_paths() {
+        let mut value = json!([{
+            "number": "2020-001",
+            "notes": "x",
+            "client": { "company": "Acme", "website_url": "acme.com" },
+        }]);
+        Paths::parse("number,client.company").select(&mut value);
+        assert_eq!(
+            value,
+            json!([{ "number": "2020-001", "client": { "company": "Acme" } }])
+        );
+    }
+
+    #[test]
+    fn fields_of_a_link_expand_it() {
+        let query = Query {
+            fields: Some("number,client.company".to_owned()),
+            ..Query::default()
+        };
+        let scope = query.scope().unwrap();
+        assert!(scope.link("client").is_some());
+        assert!(scope.link("items").is_none());
+        assert!(Scope::default().link("client").is_none());
+    }
+
+    #[test]
+    fn enter_errors_on_a_cycle() {
+        let mut scope = Scope::default();
+        scope.enter("Clients", "recCLIENT00000001", 5).unwrap();
+        scope.enter("Invoice", "recINVOICE0000001", 5).unwrap();
+        match scope.enter("Clients", "recCLIENT00000001", 5) {
+            Err(Error::Cycle { path }) => assert_eq!(
+                path,
+                vec![
+                    "Clients recCLIENT00000001",
+                    "Invoice recINVOICE0000001",
+                    "Clients recCLIENT00000001",
+                ]
+            ),
+            result => panic!("the cycle was {:?}", result),
+        }
+        // the failed `enter` isn't left on the path
+        assert_eq!(scope.path().len(), 2);
+        scope.leave();
+        scope.enter("Invoice", "recINVOICE0000001", 5).unwrap();
+    }
+
+    #[test]
+    fn the_same_record_in_another_scope_is_not_a_cycle() {
+        let mut scope = Scope::default();
+        scope.enter("Clients", "recCLIENT00000001", 5).unwrap();
+        scope.expand = Paths::parse("invoices");
+        scope.enter("Clients", "recCLIENT00000001", 5).unwrap();
+    }
+
+    #[test]
+    fn enter_errors_past_max_depth() {
+        let mut scope = Scope::default();
+        scope.enter("Invoice", "recINVOICE0000001", 2).unwrap();
+        scope.enter("Invoice Item", "recITEM000000001", 2).unwrap();
+        match scope.enter("Invoice Rates", "recRATE000000001", 2) {
+            Err(Error::TooDeep { max_depth, path }) => {
+                assert_eq!(max_depth, 2);
+                assert_eq!(path.len(), 3);
+            }
+            result => panic!("the third record was {:?}", result),
+        }
+        assert_eq!(scope.path().len(), 2);
+    }
+}
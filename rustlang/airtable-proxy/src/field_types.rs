@@ -0,0 +1,191 @@
+//! Types for the values of airtable's fields, beyond strings and numbers,
+//! and the transforms to format them.
+//!
+//! These can be used directly in the `fields` of a `gen_airtable_schema!`,
+//! for example:
+//!
+//! ```ignore
+//! total(Currency) -> String {
+//!     source = "Total Amount";
+//!     exec = format_currency;
+//! }
+//! ```
+#![allow(unused)]
+
+use crate::error::Error;
+use crate::pure;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::RoundingStrategy;
+use serde::{Deserialize, Serialize};
+
+pub use crate::expand::Link;
+
+/// A date field, without a time, `"2020-01-31"`.
+pub type Date = chrono::NaiveDate;
+
+/// A date field that includes a time, which airtable always sends as UTC.
+pub type DateTime = chrono::DateTime<chrono::Utc>;
+
+/// A number field with decimal places.
+pub type Decimal = rust_decimal::Decimal;
+
+/// A currency field, airtable gives us the amount without the symbol.
+pub type Currency = Decimal;
+
+/// A percent field, airtable gives us the fraction, so `0.5` is 50%.
+pub type Percent = Decimal;
+
+/// A duration field, in seconds.
+pub type Duration = Decimal;
+
+/// A single select field is the name of the chosen option.
+pub type SingleSelect = String;
+
+/// A multiple select field is the names of all the chosen options.
+pub type MultipleSelect = Vec<String>;
+
+/// Lookup fields (and rollups over `ARRAYJOIN`, `ARRAYUNIQUE`, etc.)
+/// are arrays of the values in the linked records.
+pub type Lookup<T> = Vec<T>;
+
+/// A collaborator (user) field, airtable leaves out the `name` of one that
+/// hasn't set it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Collaborator {
+    pub id: String,
+    pub email: String,
+    pub name: Option<String>,
+}
+
+/// Formats the integer part of `val` with a `,` every three digits,
+/// and rounds to `places` decimals.
+fn with_separators(val: Decimal, places: usize) -> String {
+    let rounded = val.round_dp_with_strategy(places as u32, RoundingStrategy::MidpointAwayFromZero);
+    let formatted = format!("{:.*}", places, rounded.abs());
+    let (int, frac) = match formatted.find('.') {
+        Some(idx) => formatted.split_at(idx),
+        None => (formatted.as_str(), ""),
+    };
+    let mut buf = String::with_capacity(formatted.len() + int.len() / 3 + 1);
+    if rounded.is_sign_negative() && !rounded.is_zero() {
+        buf.push('-');
+    }
+    for (idx, c) in int.chars().enumerate() {
+        if idx > 0 && (int.len() - idx) % 3 == 0 {
+            buf.push(',');
+        }
+        buf.push(c);
+    }
+    buf.push_str(frac);
+    buf
+}
+
+pure!(format_date(val: Date) -> String { val.format("%B %-d, %Y").to_string() });
+
+pure!(format_datetime(val: DateTime) -> String {
+    val.format("%B %-d, %Y %H:%M UTC").to_string()
+});
+
+pure!(format_decimal(val: Decimal) -> String { with_separators(val, 2) });
+
+pure!(
+    fn format_currency(val: Currency) -> String {
+        let formatted = with_separators(val, 2);
+        Ok(match formatted.strip_prefix('-') {
+            Some(positive) => format!("-${}", positive),
+            None => format!("${}", formatted),
+        })
+    }
+);
+
+pure!(format_percent(val: Percent) -> String {
+    format!("{}%", (val * Decimal::from(100)).normalize())
+});
+
+pure!(
+    fn format_duration(val: Duration) -> String {
+        let seconds = match val.round().to_u64() {
+            Some(seconds) => seconds,
+            None => {
+                return Err(Error::Transform {
+                    message: "could not format a negative or too large duration",
+                })
+            }
+        };
+        let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        Ok(if seconds == 0 {
+            format!("{}:{:02}", hours, minutes)
+        } else {
+            format!("{}:{:02}:{:02}", hours, minutes, seconds)
+        })
+    }
+);
+
+pure!(join_select(val: MultipleSelect) -> String { val.join(", ") });
+
+pure!(collaborator_name(val: Collaborator) -> String { val.name.unwrap_or(val.email) });
+
+pure!(collaborator_names(val: Vec<Collaborator>) -> Vec<String> {
+    val.into_iter().map(|c| c.name.unwrap_or(c.email)).collect()
+});
+
+pure!(join<T: ToString>(val: Lookup<T>) -> String {
+    val.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")
+});
+
+pure!(sum(val: Lookup<Decimal>) -> Decimal { val.into_iter().sum() });
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::airtable::FetchCtx;
+    use crate::config::Config;
+
+    fn decimal(val: &str) -> Decimal {
+        val.parse().unwrap()
+    }
+
+    #[test]
+    fn separators() {
+        assert_eq!(with_separators(decimal("0"), 2), "0.00");
+        assert_eq!(with_separators(decimal("999.999"), 2), "1,000.00");
+        assert_eq!(with_separators(decimal("1234567.891"), 2), "1,234,567.89");
+        assert_eq!(with_separators(decimal("-1234.5"), 0), "-1,235");
+        assert_eq!(with_separators(decimal("123456"), 0), "123,456");
+        assert_eq!(with_separators(decimal("-0.001"), 2), "0.00");
+    }
+
+    #[tokio::test]
+    async fn currencies() {
+        let ctx = FetchCtx::new(Config::default()).unwrap();
+        let currency = |val| format_currency(&ctx, decimal(val));
+        assert_eq!(currency("1234.5").await.unwrap(), "$1,234.50");
+        assert_eq!(currency("-1234.5").await.unwrap(), "-$1,234.50");
+        assert_eq!(currency("0.005").await.unwrap(), "$0.01");
+    }
+
+    #[tokio::test]
+    async fn durations() {
+        let ctx = FetchCtx::new(Config::default()).unwrap();
+        let duration = |val| format_duration(&ctx, decimal(val));
+        assert_eq!(duration("0").await.unwrap(), "0:00");
+        assert_eq!(duration("5400").await.unwrap(), "1:30");
+        assert_eq!(duration("3661.4").await.unwrap(), "1:01:01");
+        assert_eq!(duration("90000").await.unwrap(), "25:00");
+        assert!(duration("-60").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn collaborators_without_a_name_are_their_email() {
+        let ctx = FetchCtx::new(Config::default()).unwrap();
+        let collaborators: Vec<Collaborator> = serde_json::from_value(serde_json::json!([
+            { "id": "usrA", "email": "a@x.com", "name": "A" },
+            { "id": "usrB", "email": "b@x.com" },
+        ]))
+        .unwrap();
+        assert_eq!(
+            collaborator_names(&ctx, collaborators).await.unwrap(),
+            ["A", "b@x.com"]
+        );
+    }
+}
@@ -1,6 +1,88 @@
+use crate::airtable::Source;
+use crate::error::Error;
+use percent_encoding::percent_decode_str;
+
+/// Pairs of `(module name, table source)` for the tables in a schema.
+pub type Sources = &'static [(&'static str, Source)];
+
+/// Pairs of `(module name, endpoint names)` for the tables in a schema.
+pub type Endpoints = &'static [(&'static str, &'static [&'static str])];
+
+/// The segments of an endpoint's path after the module, url decoded. One
+/// that decodes to more of a path, or a query, like `a%2Fb` or `..`, is an
+/// `InvalidArgument` of the `endpoint`, since its segments end up in urls.
+pub fn segments(endpoint: &'static str, path: &str) -> Result<Vec<String>, Error> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            let decoded = percent_decode_str(segment).decode_utf8_lossy();
+            match decoded.as_ref() {
+                "." | ".." => None,
+                decoded if decoded.contains(&['/', '\\', '?', '#'][..]) => None,
+                decoded => Some(decoded.to_owned()),
+            }
+            .ok_or_else(|| Error::InvalidArgument {
+                endpoint,
+                arg: segment.to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// The reply of a generated endpoint, the errors that are the request's
+/// mistake are a 400 saying what it is, the rest are rejections.
+pub fn respond(
+    result: Result<serde_json::Value, Error>,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    use warp::http::StatusCode;
+    use warp::reply::{json, with_status};
+    let e = match result {
+        Ok(value) => return Ok(with_status(json(&value), StatusCode::OK)),
+        Err(e) => e,
+    };
+    match e.root() {
+        Error::UnknownField { valid, .. } => Ok(with_status(
+            json(&serde_json::json!({ "error": e.root().to_string(), "valid": valid })),
+            StatusCode::BAD_REQUEST,
+        )),
+        Error::InvalidQuery { .. } | Error::InvalidArgument { .. } => Ok(with_status(
+            json(&serde_json::json!({ "error": e.root().to_string() })),
+            StatusCode::BAD_REQUEST,
+        )),
+        Error::TooDeep { path, .. } => Ok(with_status(
+            json(&serde_json::json!({ "error": e.root().to_string(), "path": path })),
+            StatusCode::BAD_REQUEST,
+        )),
+        Error::Cycle { path } => Ok(with_status(
+            json(&serde_json::json!({ "error": e.root().to_string(), "path": path })),
+            StatusCode::LOOP_DETECTED,
+        )),
+        Error::NotFound { .. } => Ok(with_status(
+            json(&serde_json::json!({ "error": e.root().to_string() })),
+            StatusCode::NOT_FOUND,
+        )),
+        _ => Err(warp::reject::custom(e)),
+    }
+}
+
 pub trait Table {
     const NAME: &'static str;
     const MODULE_NAME: &'static str;
+    /// The name of the `Mapped` type, its component in the OpenAPI spec.
+    const TYPE_NAME: &'static str;
+    /// The names of the fields of the `Mapped` type, which `?fields=` can ask for.
+    const FIELD_NAMES: &'static [&'static str];
+    /// Pairs of `(field name, column)` for the fields that are read from airtable.
+    const COLUMNS: &'static [(&'static str, &'static str)];
+    /// The name of the base in the `Config` this table is in.
+    const BASE: &'static str = crate::config::DEFAULT;
+    /// The name of the key in the `Config` used to read this table.
+    const KEY: &'static str = crate::config::DEFAULT;
+    const SOURCE: Source = Source {
+        base: Self::BASE,
+        key: Self::KEY,
+        table: Self::NAME,
+    };
     type Fields: serde::de::DeserializeOwned;
 }
 
@@ -15,6 +97,7 @@ macro_rules! __gen_inner {
             use $crate::airtable::FetchCtx;
             use $crate::compose;
             use $crate::error::Error;
+            use $crate::field_types::*;
             use $crate::gen_schema::Table;
             use $crate::network::request::*;
             use $crate::network::response::One;
@@ -25,18 +108,84 @@ macro_rules! __gen_inner {
             // TODO: comment/splanations
             $(__gen_inner!{@table $name, std::stringify!($name), ($table) -> $out { $($inner)* }})*
 
-            /// Generated `warp::Filter` for all endpoints created by the schema.
-            pub fn route(ctx: $crate::ctx::Ctx) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
-                use warp::Filter;
-                let ctx_cache = $crate::ctx::ctx_cache::route(ctx.clone());
-                build_route!(ctx, ctx_cache, [ $( $name::endpoints::route ),* ])
+            __gen_inner!{@schema $($name)*}
+        }
+    };
+
+    //
+    // The parts of the `gen` module that are about the whole schema,
+    // for tables from the DSL, or `#[derive(AirtableTable)]`, each `$name`
+    // is a module with its `Mapped` type and `endpoints`.
+    (
+        @schema $($name:ident)*
+    ) => {
+        /// Where each of the tables in the schema lives.
+        pub const SOURCES: $crate::gen_schema::Sources = &[
+            $( (std::stringify!($name), <$name::Mapped as Table>::SOURCE) ),*
+        ];
+
+        /// The endpoints of each of the modules in the schema.
+        pub const ENDPOINTS: $crate::gen_schema::Endpoints = &[
+            $( (std::stringify!($name), $name::endpoints::NAMES) ),*
+        ];
+
+        /// Runs the `endpoint` of the `module` with `arg` (and the JSON `body`), without a server.
+        pub async fn get(ctx: &mut FetchCtx, module: &str, endpoint: &str, arg: &str, body: Option<&str>) -> Result<$crate::serde_json::Value, Error> {
+            match module {
+                $(std::stringify!($name) => $name::endpoints::get(ctx, endpoint, arg, body).await,)*
+                _ => Err(Error::UnknownEndpoint {
+                    module: module.to_owned(),
+                    endpoint: endpoint.to_owned(),
+                }),
+            }
+        }
+
+        /// All of the routes of the schema, for the OpenAPI spec.
+        pub fn operations() -> Vec<$crate::openapi::Operation> {
+            let mut operations = $crate::openapi::schema_operations();
+            $(operations.extend($name::endpoints::operations());)*
+            operations
+        }
+
+        /// The `Mapped` types of the schema, by name, for the OpenAPI spec.
+        pub fn components() -> Vec<(&'static str, $crate::shape::Shape)> {
+            std::vec![ $( (<$name::Mapped as Table>::TYPE_NAME, $name::object()) ),* ]
+        }
+
+        /// The schema, to mount it with a `ProxyBuilder`.
+        pub struct Schema;
+
+        impl $crate::builder::Schema for Schema {
+            const SOURCES: $crate::gen_schema::Sources = SOURCES;
+            const ENDPOINTS: $crate::gen_schema::Endpoints = ENDPOINTS;
+            fn route(ctx: $crate::ctx::Ctx) -> $crate::builder::Route {
+                $crate::builder::boxed(route(ctx))
+            }
+            fn operations() -> Vec<$crate::openapi::Operation> {
+                operations()
+            }
+            fn components() -> Vec<(&'static str, $crate::shape::Shape)> {
+                components()
             }
         }
+
+        /// Generated `warp::Filter` for all endpoints created by the schema.
+        pub fn route(ctx: $crate::ctx::Ctx) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+            use warp::Filter;
+            let ctx_cache = $crate::ctx::ctx_cache::route(ctx.clone())
+                .or($crate::ctx::ctx_attachments::route(ctx.clone(), SOURCES))
+                .or($crate::webhook::route(ctx.clone()));
+            build_route!(ctx, ctx_cache, [ $( $name::endpoints::route ),* ])
+        }
     };
 
     (
         @table $mod_name:ident, $mod_str_name:expr, ($table:expr) -> $type:ident {
+            $(base = $base:expr;)?
+            $(key = $key:expr;)?
             $(fields { $($fields:tt)* })?
+            $(computed { $($computed:tt)* })?
+            $(reverse { $($reverse:tt)* })?
             $(module { $($module:tt)* })?
             $(endpoints { $($endpoints:tt)* })?
         }
@@ -46,7 +195,10 @@ macro_rules! __gen_inner {
             use super::*;
 
             // generate the fields and structs for mapping/transformation
-            $(__gen_inner!{@fields $mod_str_name, $table, [ $($fields)* ]})?
+            __gen_inner!{
+                @fields $mod_str_name, $table, std::stringify!($type), base [ $($base)? ], key [ $($key)? ],
+                [ $($($fields)*)? ], computed [ $($($computed)*)? ], reverse [ $($($reverse)*)? ]
+            }
 
             // insert any module that's been done there, inlined
             $($($module)*)?
@@ -72,14 +224,357 @@ macro_rules! __gen_inner {
         /// Generated type alias for `Mapped` in the module.
         type $type = $mod_name::Mapped;
     };
+    //
+    // Munches the rest of a field's block, after its `source`, which can have
+    // `default = ...;`, `required;`, `link;`, `filter;`, `unique;`, and
+    // `exec = ...;`, and then generates the value of the field for
+    // `create_one`, its filter for `filters`, or its column for
+    // `unique_column`, by the `$mode`.
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], default = $new_default:expr; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $new_default ], required [ $($required)? ], link [ $($link)? ],
+            filter [ $($filter)? ], unique [ $($unique)? ], exec [ $($exec),* ], $($rest)*
+        )
+    };
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], required; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $($default)? ], required [ required ], link [ $($link)? ],
+            filter [ $($filter)? ], unique [ $($unique)? ], exec [ $($exec),* ], $($rest)*
+        )
+    };
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], link; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $($default)? ], required [ $($required)? ], link [ link ],
+            filter [ $($filter)? ], unique [ $($unique)? ], exec [ $($exec),* ], $($rest)*
+        )
+    };
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], filter; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $($default)? ], required [ $($required)? ], link [ $($link)? ],
+            filter [ filter ], unique [ $($unique)? ], exec [ $($exec),* ], $($rest)*
+        )
+    };
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], unique; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $($default)? ], required [ $($required)? ], link [ $($link)? ],
+            filter [ $($filter)? ], unique [ unique ], exec [ $($exec),* ], $($rest)*
+        )
+    };
+    (
+        @field $mode:ident [ $($args:tt)* ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ], exec = $($new_exec:expr),*; $($rest:tt)*
+    ) => {
+        __gen_inner!(
+            @field $mode [ $($args)* ], $name, $rename,
+            default [ $($default)? ], required [ $($required)? ], link [ $($link)? ],
+            filter [ $($filter)? ], unique [ $($unique)? ], exec [ $($new_exec),* ], $($rest)*
+        )
+    };
+    (
+        @field create [ $ctx:ident, $one:ident, $table:expr ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ],
+    ) => {{
+        let id = &$one.id;
+        let missing = || Error::Create {
+            table: $table,
+            id: id.clone(),
+            field: std::stringify!($name),
+            source: Box::new(Error::MissingValue { column: $rename }),
+        };
+        let value = match $one.fields.$name {
+            Some(value) => value,
+            None => __gen_inner!(@missing missing, [ $($default)? ], [ $($required)? ]),
+        };
+        let result = __gen_inner!(@link [ $($link)? ], $ctx, $name, value, [ $($exec),* ]);
+        match result {
+            Ok(val) => val,
+            Err(e) => return Err(Error::Create {
+                table: $table,
+                id: id.clone(),
+                field: std::stringify!($name),
+                source: Box::new(e),
+            })
+        }
+    }};
+    (
+        @field filter [ $query:ident, $filters:ident, $type:ty ], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ],
+    ) => {
+        __gen_inner!(@filter [ $($filter)? ], [ $($link)? ], $query, $filters, $type, $name, $rename)
+    };
+    (
+        @field unique [], $name:ident, $rename:expr,
+        default [ $($default:expr)? ], required [ $($required:ident)? ], link [ $($link:ident)? ],
+        filter [ $($filter:ident)? ], unique [ $($unique:ident)? ], exec [ $($exec:expr),* ],
+    ) => {
+        __gen_inner!(@unique [ $($unique)? ], $rename)
+    };
+    //
+    // A `filter;` field is compared in a formula, or for a `link;`, by the
+    // IDs of the linked records.
+    ( @filter [ ], [ $($link:ident)? ], $query:ident, $filters:ident, $type:ty, $name:ident, $rename:expr ) => {
+        ()
+    };
+    ( @filter [ filter ], [ ], $query:ident, $filters:ident, $type:ty, $name:ident, $rename:expr ) => {
+        $filters.filter::<$type>($query, std::stringify!($name), $rename)?
+    };
+    ( @filter [ filter ], [ link ], $query:ident, $filters:ident, $type:ty, $name:ident, $rename:expr ) => {
+        $filters.link($query, std::stringify!($name), $rename)
+    };
+    //
+    // A `unique;` field's column is where the batch looks up its keys.
+    ( @unique [ ], $rename:expr ) => { None };
+    ( @unique [ unique ], $rename:expr ) => { Some($rename) };
+    //
+    // A `link;` field is the IDs of its linked records, unless it's expanded
+    // (or its fields are asked for), then the `exec` that hydrates them is run
+    // in the scope under it.
+    ( @link [ ], $ctx:ident, $name:ident, $value:ident, [ $($exec:expr),* ]) => {
+        compose!($ctx, $value, [ $($exec),* ])
+    };
+    ( @link [ link ], $ctx:ident, $name:ident, $value:ident, [ $($exec:expr),* ]) => {
+        match $ctx.scope().link(std::stringify!($name)) {
+            None => Ok(Link::Ids($value)),
+            Some(scope) => {
+                let parent = $ctx.set_scope(scope);
+                let result = compose!($ctx, $value, [ $($exec),* ]);
+                $ctx.set_scope(parent);
+                result.map(Link::Expanded)
+            }
+        }
+    };
+    ( @missing $missing:ident, [ $default:expr ], [ required ]) => {
+        std::compile_error!("a field is either `required` or has a `default`, not both")
+    };
+    ( @missing $missing:ident, [ $default:expr ], [ ]) => { $default };
+    ( @missing $missing:ident, [ ], [ required ]) => { return Err($missing()) };
+    // without either, only an `Option` (or anything else that can be
+    // deserialized from a `null`) can be missing
+    ( @missing $missing:ident, [ ], [ ]) => {
+        match $crate::serde_json::from_value($crate::serde_json::Value::Null) {
+            Ok(value) => value,
+            Err(_) => return Err($missing()),
+        }
+    };
+    //
+    // A `computed` field is made from `&Fields` before the fields are, or
+    // `&Partial` after, each of those is a phase of `create_one`, where
+    // the field is computed if it's from that phase.
+    (
+        @computed fields, $ctx:ident, $one:ident, $partial:ident, $table:expr,
+        $computed:ident, fields, [ $($exec:expr),+ ]
+    ) => {
+        let $computed = __gen_inner!(@compute $ctx, &$one.fields, $one, $table, $computed, [ $($exec),+ ]);
+    };
+    (
+        @computed mapped, $ctx:ident, $one:ident, $partial:ident, $table:expr,
+        $computed:ident, mapped, [ $($exec:expr),+ ]
+    ) => {
+        let $computed = __gen_inner!(@compute $ctx, &$partial, $one, $table, $computed, [ $($exec),+ ]);
+    };
+    (
+        @computed $phase:ident, $ctx:ident, $one:ident, $partial:ident, $table:expr,
+        $computed:ident, fields, [ $($exec:expr),+ ]
+    ) => {};
+    (
+        @computed $phase:ident, $ctx:ident, $one:ident, $partial:ident, $table:expr,
+        $computed:ident, mapped, [ $($exec:expr),+ ]
+    ) => {};
+    (
+        @computed fields, $ctx:ident, $one:ident, $partial:ident, $table:expr,
+        $computed:ident, $from:ident, [ $($exec:expr),+ ]
+    ) => {
+        std::compile_error!(std::concat!(
+            "the computed field `", std::stringify!($computed),
+            "` has to be `from = fields;` or `from = mapped;`"
+        ));
+    };
+    (
+        @computed mapped, $($rest:tt)*
+    ) => {};
+    //
+    // A `reverse` field is the records of another table that link to this
+    // one, by their link column, `source`, or this table's `inverse` of it,
+    // fetched when it's expanded, see `reverse`.
+    ( @reverse_by [ $source:expr ], [ ] ) => { $crate::reverse::Reverse::Link($source) };
+    ( @reverse_by [ ], [ $inverse:expr ] ) => { $crate::reverse::Reverse::Inverse($inverse) };
+    ( @reverse_by [ $($source:expr)? ], [ $($inverse:expr)? ] ) => {
+        std::compile_error!("a reverse field has either a `source` or an `inverse`")
+    };
+    (
+        @reverse $ctx:ident, $one:ident, $table:expr, $reverse:ident, $type:ty, $by:expr
+    ) => {
+        match $ctx.scope().link(std::stringify!($reverse)) {
+            None => None,
+            Some(scope) => {
+                let parent = $ctx.set_scope(scope);
+                let id = &$one.id;
+                let result = async {
+                    let many = $crate::reverse::fetch::<Mapped, $type>($ctx, id, $by).await?;
+                    reverse_fields::$reverse($ctx, many).await
+                }
+                .await;
+                $ctx.set_scope(parent);
+                match result {
+                    Ok(records) => Some(records),
+                    Err(e) => return Err(Error::Create {
+                        table: $table,
+                        id: $one.id.clone(),
+                        field: std::stringify!($reverse),
+                        source: Box::new(e),
+                    }),
+                }
+            }
+        }
+    };
+    (
+        @compute $ctx:ident, $arg:expr, $one:ident, $table:expr, $computed:ident, [ $($exec:expr),+ ]
+    ) => {
+        match compose!($ctx, $arg, [ $($exec),+ ]) {
+            Ok(val) => val,
+            Err(e) => return Err(Error::Create {
+                table: $table,
+                id: $one.id.clone(),
+                field: std::stringify!($computed),
+                source: Box::new(e),
+            })
+        }
+    };
+    //
+    // The parts of an endpoint, see `@endpoints`.
+    ( @or_unit $type:ty) => { $type };
+    ( @or_unit ) => { () };
+    ( @method ) => { warp::get() };
+    ( @method GET ) => { warp::get() };
+    ( @method POST ) => { warp::post() };
+    ( @method PUT ) => { warp::put() };
+    ( @method PATCH ) => { warp::patch() };
+    ( @method DELETE ) => { warp::delete() };
+    ( @method_name ) => { "GET" };
+    ( @method_name $method:ident ) => { std::stringify!($method) };
+    ( @display | $($tokens:tt)* ) => { std::stringify!($($tokens)*) };
+    ( @display $display:literal | $($tokens:tt)* ) => { $display };
+    ( @segment $literal:literal ) => { $crate::openapi::Segment::Literal($literal) };
+    ( @segment $type:ident ) => { $crate::openapi::Segment::Param("arg", <$type>::shape()) };
+    ( @query_params $query:ty ) => { $crate::openapi::query_params(<$query>::shape()) };
+    ( @query_params ) => { std::vec![] };
+    ( @body_shape $body:ty ) => { Some(<$body>::shape()) };
+    ( @body_shape ) => { None };
+    ( @query_filter $query:ty ) => { warp::query::<$query>() };
+    ( @query_filter ) => { warp::any().map(|| ()) };
+    ( @body_filter $body:ty ) => { warp::body::json::<$body>() };
+    ( @body_filter ) => { warp::any().map(|| ()) };
+    ( @query_parse $query:ident, $type:ty ) => { $crate::serde_urlencoded::from_str::<$type>($query) };
+    ( @query_parse $query:ident, ) => { Ok::<_, Error>(()) };
+    ( @body_parse $body:ident, $type:ty ) => {
+        $crate::serde_json::from_str::<$type>($body.unwrap_or("null"))
+    };
+    ( @body_parse $body:ident, ) => { Ok::<_, Error>(()) };
+    ( @keys ) => { $crate::keys::Keys::Fields };
+    ( @keys fields ) => { $crate::keys::Keys::Fields };
+    ( @keys source ) => { $crate::keys::Keys::Source };
+    ( @keys camelCase ) => { $crate::keys::Keys::CamelCase };
+    ( @keys snake_case ) => { $crate::keys::Keys::SnakeCase };
+    //
+    // Munches the segments of a path, `$i` counts them, checking the literal
+    // ones, and parsing the typed ones, to `check` that the path matches, or
+    // make the `arg` from those, and the query and body.
+    (
+        @path $mode:ident, $segments:ident, [ $($i:tt)* ], [ $($check:expr,)* ], [ $($param:expr,)* ],
+        [ $literal:literal $($rest:tt)* ] $($parts:tt)*
+    ) => {
+        __gen_inner!(
+            @path $mode, $segments, [ $($i)* 1 ],
+            [ $($check,)* $segments.get(0 $(+ $i)*).map(String::as_str) == Some($literal), ],
+            [ $($param,)* ], [ $($rest)* ] $($parts)*
+        )
+    };
+    (
+        @path $mode:ident, $segments:ident, [ $($i:tt)* ], [ $($check:expr,)* ], [ $($param:expr,)* ],
+        [ $type:ident $($rest:tt)* ] $($parts:tt)*
+    ) => {
+        __gen_inner!(
+            @path $mode, $segments, [ $($i)* 1 ], [ $($check,)* ],
+            [ $($param,)* $segments.get(0 $(+ $i)*).and_then(|segment| segment.parse::<$type>().ok()), ],
+            [ $($rest)* ] $($parts)*
+        )
+    };
+    (
+        @path check, $segments:ident, [ $($i:tt)* ], [ $($check:expr,)* ], [ $($param:expr,)* ], [ ]
+    ) => {
+        $segments.len() == 0 $(+ $i)* $(&& $check)* $(&& $param.is_some())*
+    };
+    (
+        @path arg, $segments:ident, [ $($i:tt)* ], [ $($check:expr,)* ], [ $($param:expr,)* ], [ ],
+        $($parts:tt)*
+    ) => {
+        __gen_inner!(@tuple [ $($param?,)* ] $($parts)*)
+    };
+    ( @tuple [ $($e:expr,)* ] query [ $type:ty ] $query:ident, $($rest:tt)* ) => {
+        __gen_inner!(@tuple [ $($e,)* $query, ] $($rest)*)
+    };
+    ( @tuple [ $($e:expr,)* ] query [ ] $query:ident, $($rest:tt)* ) => {
+        __gen_inner!(@tuple [ $($e,)* ] $($rest)*)
+    };
+    ( @tuple [ $($e:expr,)* ] body [ $type:ty ] $body:ident ) => {
+        __gen_inner!(@tuple [ $($e,)* $body, ])
+    };
+    ( @tuple [ $($e:expr,)* ] body [ ] $body:ident ) => {
+        __gen_inner!(@tuple [ $($e,)* ])
+    };
+    ( @tuple [ ] ) => { () };
+    ( @tuple [ $e:expr, ] ) => { $e };
+    ( @tuple [ $($e:expr,)+ ] ) => { ( $($e,)+ ) };
     ( @choose_field_type $type1:ty | $type2:ty) => { $type1 };
     ( @choose_field_type | $type:ty) => { $type };
     ( @choose_field_type $type:ty |) => { $type };
     ( @choose_field_type |) => { String };
+    //
+    // An endpoint's path is segments after the module, each a literal, or a
+    // `FromStr` type that's parsed from it, like `url_path { "year" / u32 }`.
+    // Its typed query string, `query = T;`, and JSON body, `body = T;`, come
+    // after the types of the path, in the argument of the `exec` chain, which
+    // is a tuple of them, or only the one when there's one. Its `keys = ..;`
+    // are the keys of its response, without a `?keys=`, see `keys`. The
+    // derive passes how its input and exec are written, in `display`, since
+    // `stringify!` spaces out the tokens of a proc macro.
     (
         @endpoints $mod_str_name:expr, [
             $($name:ident ($from:ty) -> $to:ty {
-                url_path { $($path_tokens:tt)* }
+                $(display($input_display:literal, $exec_display:literal);)?
+                $(method = $method:ident;)?
+                url_path { $($segment:tt)/ * }
+                $(query = $query:ty;)?
+                $(body = $body:ty;)?
+                $(keys = $keys:ident;)?
                 $(exec = $($exec:expr),*;)?
             })*
         ]
@@ -92,36 +587,350 @@ macro_rules! __gen_inner {
             use $crate::warp;
             use $crate::warp::{Filter, Rejection, Reply};
 
+            type Params = __gen_inner!(@or_unit $($query)?);
+            type Body = __gen_inner!(@or_unit $($body)?);
+
             pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
                 warp::path($mod_str_name)
-                    .and(warp::path::param::<$from>())
-                    .and(warp::get())
+                    .and(warp::path::tail())
+                    .and_then(|tail: warp::path::Tail| async move {
+                        // a bad segment is the request's mistake, for `run` to reply with
+                        match $crate::gen_schema::segments(std::stringify!($name), tail.as_str()) {
+                            Ok(segments) if path_matches(&segments) => Ok(Ok(segments)),
+                            Ok(_) => Err(warp::reject::not_found()),
+                            Err(e) => Ok(Err(e)),
+                        }
+                    })
+                    .and(__gen_inner!(@method $($method)?))
+                    .and(__gen_inner!(@query_filter $($query)?))
+                    .and(__gen_inner!(@body_filter $($body)?))
+                    .and(warp::query::<$crate::expand::Query>())
                     .and(with_ctx(ctx))
                     .and_then(run)
             }
 
+            /// Whether the path after the module is this endpoint's.
+            fn path_matches(segments: &[String]) -> bool {
+                __gen_inner!(@path check, segments, [], [], [], [ $($segment)* ])
+            }
+
+            /// The argument of the `exec` chain, from the parts of the request.
+            fn exec_arg(segments: &[String], params: Params, body: Body) -> Option<$from> {
+                Some(__gen_inner!(
+                    @path arg, segments, [], [], [], [ $($segment)* ],
+                    query [ $($query)? ] params, body [ $($body)? ] body
+                ))
+            }
+
             async fn handler(ctx: &mut FetchCtx, arg: $from) -> Result<$to, Error> {
-                trace!("exec [{}] with arg={}", std::stringify!( $($($exec),*)? ), arg);
+                trace!("exec [{}]", std::stringify!( $($($exec),*)? ));
                 compose!(ctx, arg, [ $($($exec),*)? ])
             }
 
-            pub async fn run(arg: $from, ctx: Ctx) -> Result<impl Reply, Rejection> {
+            pub async fn run(
+                segments: Result<Vec<String>, Error>,
+                params: Params,
+                body: Body,
+                query: $crate::expand::Query,
+                ctx: Ctx,
+            ) -> Result<impl Reply, Rejection> {
+                let segments = match segments {
+                    Ok(segments) => segments,
+                    Err(e) => return $crate::gen_schema::respond(Err(e)),
+                };
+                trace!("{} with path={:?}", std::stringify!($name), segments);
+                let arg = match exec_arg(&segments, params, body) {
+                    Some(arg) => arg,
+                    None => return Err(warp::reject::not_found()),
+                };
+                let scope = match query.scope() {
+                    Ok(scope) => scope,
+                    Err(e) => return $crate::gen_schema::respond(Err(e)),
+                };
                 let mut c = ctx.lock().await;
-                match handler(&mut c, arg).await {
-                    Ok(val) => Ok(warp::reply::json(&val)),
-                    Err(e) => Err(warp::reject::custom(e))
+                c.set_scope(scope.clone());
+                let result = handler(&mut c, arg).await;
+                c.end_request();
+                $crate::gen_schema::respond(result.and_then(|val| {
+                    let val = $crate::serde_json::to_value(&val).map_err(Error::SerdeTransform)?;
+                    Ok(scope.render::<$to>(val, __gen_inner!(@keys $($keys)?)))
+                }))
+            }
+
+            /// What the endpoint takes and returns, for the spec.
+            pub fn operation() -> $crate::openapi::Operation {
+                use $crate::shape::Describe;
+                let path = std::vec![
+                    $crate::openapi::Segment::Literal($mod_str_name),
+                    $(__gen_inner!(@segment $segment),)*
+                ];
+                $crate::openapi::Operation {
+                    method: __gen_inner!(@method_name $($method)?),
+                    query: __gen_inner!(@query_params $($query)?),
+                    body: __gen_inner!(@body_shape $($body)?),
+                    input: Some(__gen_inner!(@display $($input_display)? | $from)),
+                    exec: Some(__gen_inner!(@display $($exec_display)? | $($($exec),*)?)),
+                    ..$crate::openapi::Operation::endpoint::<Mapped>(std::stringify!($name), path, <$to>::shape())
                 }
             }
 
+            /// Runs the endpoint without a server, the `arg` is the path after
+            /// the module, with the query string, and the `body` is JSON.
+            pub async fn get(ctx: &mut FetchCtx, arg: &str, body: Option<&str>) -> Result<$crate::serde_json::Value, Error> {
+                let invalid = || Error::InvalidArgument {
+                    endpoint: std::stringify!($name),
+                    arg: arg.to_owned(),
+                };
+                let (path, query) = match arg.find('?') {
+                    Some(at) => (&arg[..at], &arg[at + 1..]),
+                    None => (arg, ""),
+                };
+                let segments = $crate::gen_schema::segments(std::stringify!($name), path)?;
+                let params: Params = __gen_inner!(@query_parse query, $($query)?).map_err(|_| invalid())?;
+                let body: Body = __gen_inner!(@body_parse body, $($body)?).map_err(|_| invalid())?;
+                let arg = match path_matches(&segments) {
+                    true => exec_arg(&segments, params, body).ok_or_else(invalid)?,
+                    false => return Err(invalid()),
+                };
+                let val = handler(ctx, arg).await?;
+                let val = $crate::serde_json::to_value(&val).map_err(Error::SerdeTransform)?;
+                Ok(ctx.scope().render::<$to>(val, __gen_inner!(@keys $($keys)?)))
+            }
         })*
 
+        /// `GET /{module}`, which lists the records of the table, see `list`.
+        pub mod list {
+            #![allow(unused)]
+            use super::*;
+            use $crate::ctx::with_ctx;
+            use $crate::warp::{Filter, Rejection, Reply};
+            use std::collections::HashMap;
+
+            pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+                warp::path($mod_str_name)
+                    .and(warp::path::end())
+                    .and(warp::get())
+                    .and(warp::query::<HashMap<String, String>>())
+                    .and(warp::query::<$crate::expand::Query>())
+                    .and(with_ctx(ctx))
+                    .and_then(run)
+            }
+
+            /// A page of the records, with the `?fields=` of them, and the
+            /// cursor of the next page.
+            async fn handler(ctx: &mut FetchCtx, query: &HashMap<String, String>) -> Result<$crate::serde_json::Value, Error> {
+                let (records, cursor) = $crate::list::list::<Mapped>(ctx, query, filters(query)?).await?;
+                let records = Mapped::create_many(ctx, records).await?;
+                let records = $crate::serde_json::to_value(&records).map_err(Error::SerdeTransform)?;
+                Ok($crate::serde_json::json!({
+                    "records": ctx.scope().render::<Vec<Mapped>>(records, $crate::keys::Keys::Fields),
+                    "cursor": cursor,
+                }))
+            }
+
+            pub async fn run(
+                params: HashMap<String, String>,
+                query: $crate::expand::Query,
+                ctx: Ctx,
+            ) -> Result<impl Reply, Rejection> {
+                let scope = match query.scope() {
+                    Ok(scope) => scope,
+                    Err(e) => return $crate::gen_schema::respond(Err(e)),
+                };
+                let mut c = ctx.lock().await;
+                c.set_scope(scope);
+                let result = handler(&mut c, &params).await;
+                c.end_request();
+                $crate::gen_schema::respond(result)
+            }
+
+            /// The list, for the spec.
+            pub fn operation() -> $crate::openapi::Operation {
+                let filters = filters(&HashMap::new()).unwrap_or_default();
+                $crate::list::operation::<Mapped>(filters)
+            }
+
+            /// Lists the records without a server, the `arg` is the query string.
+            pub async fn get(ctx: &mut FetchCtx, arg: &str) -> Result<$crate::serde_json::Value, Error> {
+                let query: HashMap<String, String> =
+                    $crate::serde_urlencoded::from_str(arg.trim_start_matches('?')).map_err(|_| Error::InvalidArgument {
+                        endpoint: "list",
+                        arg: arg.to_owned(),
+                    })?;
+                handler(ctx, &query).await
+            }
+        }
+
+        /// `POST /{module}/batch`, which gets the records for each of the keys
+        /// in the body, see `batch`.
+        pub mod batch {
+            #![allow(unused)]
+            use super::*;
+            use $crate::ctx::with_ctx;
+            use $crate::warp::{Filter, Rejection, Reply};
+
+            pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+                warp::path($mod_str_name)
+                    .and(warp::path("batch"))
+                    .and(warp::path::end())
+                    .and(warp::post())
+                    .and(warp::body::json::<Vec<String>>())
+                    .and(warp::query::<$crate::expand::Query>())
+                    .and(with_ctx(ctx))
+                    .and_then(run)
+            }
+
+            /// The record of one key, or its error, fetched in the `batch`.
+            async fn entry(ctx: &mut FetchCtx, key: &str, batch: &mut $crate::network::cache::Batch) -> $crate::serde_json::Value {
+                let mut ctx = ctx.batch(batch);
+                let result = async {
+                    let one = $crate::batch::fetch::<Mapped>(&mut ctx, key, unique_column()).await?;
+                    let record = Mapped::create_one(&mut ctx, one).await?;
+                    $crate::serde_json::to_value(&record).map_err(Error::SerdeTransform)
+                }
+                .await;
+                $crate::batch::entry::<Mapped>(ctx.scope(), result)
+            }
+
+            /// Each of the keys, with its record, or its error.
+            async fn handler(ctx: &mut FetchCtx, keys: Vec<String>) -> Result<$crate::serde_json::Value, Error> {
+                $crate::batch::check::<Mapped>(ctx.scope(), &keys)?;
+                let mut batch = Default::default();
+                let mut results = $crate::serde_json::Map::new();
+                for key in keys {
+                    let entry = entry(ctx, &key, &mut batch).await;
+                    results.insert(key, entry);
+                }
+                Ok($crate::serde_json::Value::Object(results))
+            }
+
+            pub async fn run(keys: Vec<String>, query: $crate::expand::Query, ctx: Ctx) -> Result<impl Reply, Rejection> {
+                let scope = match query.scope() {
+                    Ok(scope) => scope,
+                    Err(e) => return $crate::gen_schema::respond(Err(e)),
+                };
+                if let Err(e) = $crate::batch::check::<Mapped>(&scope, &keys) {
+                    return $crate::gen_schema::respond(Err(e));
+                }
+                // the ctx is locked for a key at a time, not the whole batch
+                let mut batch = Default::default();
+                let mut results = $crate::serde_json::Map::new();
+                for key in keys {
+                    let mut c = ctx.lock().await;
+                    c.set_scope(scope.clone());
+                    let entry = entry(&mut c, &key, &mut batch).await;
+                    c.end_request();
+                    results.insert(key, entry);
+                }
+                $crate::gen_schema::respond(Ok($crate::serde_json::Value::Object(results)))
+            }
+
+            /// The batch, for the spec.
+            pub fn operation() -> $crate::openapi::Operation {
+                $crate::batch::operation::<Mapped>()
+            }
+
+            /// Gets the records without a server, the `body` is the keys.
+            pub async fn get(ctx: &mut FetchCtx, body: Option<&str>) -> Result<$crate::serde_json::Value, Error> {
+                let body = body.unwrap_or_default();
+                let keys: Vec<String> = $crate::serde_json::from_str(body).map_err(|_| Error::InvalidArgument {
+                    endpoint: "batch",
+                    arg: body.to_owned(),
+                })?;
+                handler(ctx, keys).await
+            }
+        }
+
+        /// `GET /_raw/{module}/{record_id}`, the record as airtable sent it,
+        /// when the config serves it, see `raw`.
+        pub mod raw {
+            #![allow(unused)]
+            use super::*;
+            use $crate::ctx::with_ctx;
+            use $crate::warp::{Filter, Rejection, Reply};
+
+            pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+                warp::path("_raw")
+                    .and(warp::path($mod_str_name))
+                    .and(warp::path::param::<String>())
+                    .and(warp::path::end())
+                    .and(warp::get())
+                    .and(warp::query::<$crate::raw::Query>())
+                    .and(with_ctx(ctx))
+                    .and_then(run)
+            }
+
+            /// The record, or with `?compare=true`, its raw and mapped fields.
+            async fn handler(ctx: &mut FetchCtx, id: &str, query: $crate::raw::Query) -> Result<$crate::serde_json::Value, Error> {
+                let raw: $crate::raw::Raw = $crate::raw::fetch::<Mapped, _>(ctx, id).await?;
+                if !query.compare {
+                    return $crate::serde_json::to_value(&raw).map_err(Error::SerdeTransform);
+                }
+                let mapped = async {
+                    let one = $crate::raw::fields::<Mapped>(&raw)?;
+                    let record = Mapped::create_one(ctx, one).await?;
+                    $crate::serde_json::to_value(&record).map_err(Error::SerdeTransform)
+                }
+                .await;
+                Ok($crate::raw::compare::<Mapped>(raw, mapped))
+            }
+
+            pub async fn run(id: String, query: $crate::raw::Query, ctx: Ctx) -> Result<impl Reply, Rejection> {
+                let mut c = ctx.lock().await;
+                if !c.raw_endpoint() {
+                    return Err(warp::reject::not_found());
+                }
+                $crate::gen_schema::respond(handler(&mut c, &id, query).await)
+            }
+
+            /// The raw records, for the spec.
+            pub fn operation() -> $crate::openapi::Operation {
+                $crate::raw::operation::<Mapped>()
+            }
+
+            /// The record without a server, the `arg` is its ID, and the query string.
+            pub async fn get(ctx: &mut FetchCtx, arg: &str) -> Result<$crate::serde_json::Value, Error> {
+                let invalid = || Error::InvalidArgument {
+                    endpoint: "raw",
+                    arg: arg.to_owned(),
+                };
+                let (path, query) = match arg.find('?') {
+                    Some(at) => (&arg[..at], &arg[at + 1..]),
+                    None => (arg, ""),
+                };
+                let query = $crate::serde_urlencoded::from_str(query).map_err(|_| invalid())?;
+                match $crate::gen_schema::segments("raw", path)?.as_slice() {
+                    [id] => handler(ctx, id, query).await,
+                    _ => Err(invalid()),
+                }
+            }
+        }
+
+        /// The names of the endpoints of this module, `list`, `batch`, and
+        /// `raw` are every table's.
+        pub const NAMES: &[&str] = &[ $(std::stringify!($name),)* "list", "batch", "raw" ];
+
+        /// The endpoints of this module, for the spec.
+        pub fn operations() -> Vec<$crate::openapi::Operation> {
+            std::vec![ $($name::operation(),)* list::operation(), batch::operation(), raw::operation() ]
+        }
+
+        pub async fn get(ctx: &mut FetchCtx, endpoint: &str, arg: &str, body: Option<&str>) -> Result<$crate::serde_json::Value, Error> {
+            match endpoint {
+                $(std::stringify!($name) => $name::get(ctx, arg, body).await,)*
+                "list" => list::get(ctx, arg).await,
+                "batch" => batch::get(ctx, body).await,
+                "raw" => raw::get(ctx, arg).await,
+                _ => Err(Error::UnknownEndpoint {
+                    module: $mod_str_name.to_owned(),
+                    endpoint: endpoint.to_owned(),
+                }),
+            }
+        }
+
         pub fn route(ctx: Ctx) -> impl warp::Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
             use warp::Filter;
-            let default_route = warp::path($mod_str_name)
-                .and(warp::get())
-                .map(|| std::format!("fine.... {}", $mod_str_name));
-
-            build_route!(ctx, [ $($name::route),* ], default_route)
+            build_route!(ctx, [ batch::route, $($name::route,)* list::route, raw::route ],)
         }
     };
     //
@@ -130,45 +939,161 @@ macro_rules! __gen_inner {
     // and we want to parse for the module, as well as the more complex
     // mapping of the fully hydrated type that will be constructed.
     (
-        @fields $mod_str_name:expr, $table:expr, [
+        @fields $mod_str_name:expr, $table:expr, $type_name:expr, base [ $($base:expr)? ], key [ $($key:expr)? ], [
             $($name:ident $(($from:ty))? $(-> $to:ty)? {
                 source = $rename:expr;
-                $(exec = $($exec:expr),*;)?
+                $($rest:tt)*
+            })*
+        ], computed [
+            $($computed:ident -> $computed_type:ty {
+                from = $computed_from:ident;
+                exec = $($computed_exec:expr),+;
+            })*
+        ], reverse [
+            $($reverse:ident -> $reverse_type:ty {
+                $(source = $reverse_source:expr;)?
+                $(inverse = $reverse_inverse:expr;)?
             })*
         ]
     ) => {
+        /// The fields of a record, as airtable sends them, which leaves
+        /// out empty cells, so each of them can be missing.
         #[derive(Debug, Deserialize)]
         pub struct Fields {
             $(
-                #[serde(rename = $rename)]
-                pub $name: __gen_inner!(@choose_field_type $($from)? | $($to)?),
+                #[serde(rename = $rename, default)]
+                pub $name: Option<__gen_inner!(@choose_field_type $($from)? | $($to)?)>,
             )*
         }
 
+        /// The `Mapped` fields that come from the record's fields, before
+        /// the `computed` ones (which can be made from these) are added.
+        #[derive(Debug)]
+        pub struct Partial {
+            $( pub $name: __gen_inner!(@choose_field_type $($to)? |),)*
+        }
+
         #[derive(Debug, Serialize)]
         pub struct Mapped {
             $( pub $name: __gen_inner!(@choose_field_type $($to)? |),)*
+            $( pub $computed: $computed_type,)*
+            $( pub $reverse: Option<Vec<$reverse_type>>,)*
         }
 
         impl Table for Mapped {
             const NAME: &'static str = $table;
             const MODULE_NAME: &'static str = $mod_str_name;
+            const TYPE_NAME: &'static str = $type_name;
+            const FIELD_NAMES: &'static [&'static str] = &[
+                $(std::stringify!($name),)* $(std::stringify!($computed),)* $(std::stringify!($reverse),)*
+            ];
+            const COLUMNS: &'static [(&'static str, &'static str)] = &[
+                $((std::stringify!($name), $rename),)*
+            ];
+            $(const BASE: &'static str = $base;)?
+            $(const KEY: &'static str = $key;)?
             type Fields = Fields;
         }
 
+        /// The `Mapped` type is its component in the spec.
+        impl $crate::shape::Describe for Mapped {
+            fn shape() -> $crate::shape::Shape {
+                $crate::shape::Shape::Ref(Self::TYPE_NAME)
+            }
+        }
+
+        /// The records of the `Mapped` type have their keys renamed, and so do
+        /// the ones they link to.
+        impl $crate::keys::Rename for Mapped {
+            fn rename(value: &mut $crate::serde_json::Value, keys: $crate::keys::Keys) {
+                use $crate::keys::rename_field;
+                if let $crate::serde_json::Value::Object(object) = value {
+                    $(rename_field::<__gen_inner!(@choose_field_type $($to)? |)>(object, std::stringify!($name), keys);)*
+                    $(rename_field::<$computed_type>(object, std::stringify!($computed), keys);)*
+                    $(rename_field::<Option<Vec<$reverse_type>>>(object, std::stringify!($reverse), keys);)*
+                    keys.rename_fields::<Self>(object);
+                }
+            }
+        }
+
+        /// The shape of the `Mapped` type, for its component in the spec.
+        pub fn object() -> $crate::shape::Shape {
+            use $crate::shape::Describe;
+            $crate::shape::Shape::Record(std::vec![
+                $((std::stringify!($name), <__gen_inner!(@choose_field_type $($to)? |)>::shape()),)*
+                $((std::stringify!($computed), <$computed_type>::shape()),)*
+                $((std::stringify!($reverse), <Option<Vec<$reverse_type>>>::shape()),)*
+            ])
+        }
+
+        /// Creates the records of each `reverse` field, boxed, since those can
+        /// link back to this table.
+        pub mod reverse_fields {
+            use super::*;
+            $(pub fn $reverse(
+                ctx: &mut FetchCtx,
+                many: $crate::network::response::List<<$reverse_type as Table>::Fields>,
+            ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<$reverse_type>, Error>> + Send + '_>> {
+                Box::pin(<$reverse_type>::create_many(ctx, many))
+            })*
+        }
+
+        /// The filters in the `query` on the `filter;` fields, for the list.
+        pub fn filters(query: &std::collections::HashMap<String, String>) -> Result<$crate::list::Filters, Error> {
+            let mut filters = $crate::list::Filters::default();
+            $(__gen_inner!(
+                @field filter [ query, filters, __gen_inner!(@choose_field_type $($from)? | $($to)?) ],
+                $name, $rename, default [], required [], link [], filter [], unique [], exec [], $($rest)*
+            );)*
+            Ok(filters)
+        }
+
+        /// The column of the first `unique;` field, which the batch looks up
+        /// the keys that aren't record IDs in.
+        pub fn unique_column() -> Option<&'static str> {
+            None $(.or(__gen_inner!(
+                @field unique [], $name, $rename,
+                default [], required [], link [], filter [], unique [], exec [], $($rest)*
+            )))*
+        }
+
         impl Mapped {
 
             pub async fn create_one(ctx: &mut FetchCtx, one: One<Fields>) -> Result<Self, Error> {
-                Ok(Self {
+                ctx.enter(Self::NAME, &one.id)?;
+                let result = Self::hydrate(ctx, one).await;
+                ctx.leave();
+                result
+            }
+
+            /// Creates the record, and the linked records it expands, while it's
+            /// on the path of the ones being created, see `create_one`.
+            async fn hydrate(ctx: &mut FetchCtx, one: One<Fields>) -> Result<Self, Error> {
+                ctx.scope().check(Self::NAME, Self::FIELD_NAMES)?;
+                $(__gen_inner!{
+                    @computed fields, ctx, one, partial, $table,
+                    $computed, $computed_from, [ $($computed_exec),+ ]
+                })*
+                let partial = Partial {
                     $(
-                        $name: match compose!(ctx, one.fields.$name, [ $($($exec),*)? ]) {
-                            Ok(val) => val,
-                            Err(e) => return Err(Error::Create {
-                                table: $table,
-                                source: Box::new(e),
-                            })
-                        }
+                        $name: __gen_inner!(
+                            @field create [ ctx, one, $table ], $name, $rename,
+                            default [], required [], link [], filter [], unique [], exec [], $($rest)*
+                        )
                      ),*
+                };
+                $(__gen_inner!{
+                    @computed mapped, ctx, one, partial, $table,
+                    $computed, $computed_from, [ $($computed_exec),+ ]
+                })*
+                $(let $reverse = __gen_inner!(
+                    @reverse ctx, one, $table, $reverse, $reverse_type,
+                    __gen_inner!(@reverse_by [ $($reverse_source)? ], [ $($reverse_inverse)? ])
+                );)*
+                Ok(Self {
+                    $( $name: partial.$name, )*
+                    $( $computed, )*
+                    $( $reverse, )*
                 })
             }
 
@@ -204,6 +1129,23 @@ macro_rules! gen_airtable_schema {
     }
 }
 
+/// Generates the `gen` module of a schema from the modules that
+/// `#[derive(AirtableTable)]` generates, like `gen_derived_schema!(invoice, invoice_client)`.
+#[macro_export]
+macro_rules! gen_derived_schema {
+    ($($name:ident),* $(,)?) => {
+        pub mod gen {
+            use super::*;
+            use $crate::airtable::FetchCtx;
+            use $crate::error::Error;
+            use $crate::gen_schema::Table;
+            use $crate::warp;
+
+            $crate::__gen_inner!{@schema $($name)*}
+        }
+    }
+}
+
 #[macro_export(local_inner_macros)]
 macro_rules! build_route {
     ($ctx:expr, [ $name:expr ], $($default:expr)?) => {
@@ -228,3 +1170,34 @@ macro_rules! build_route {
         build_route!($ctx, $r.or($name($ctx.clone())), [ $($ns),+ ])
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_are_decoded() {
+        let segments = segments("find", "/2020-001/a%20b/").unwrap();
+        assert_eq!(segments, vec!["2020-001", "a b"]);
+    }
+
+    #[test]
+    fn segments_that_decode_to_paths_are_invalid() {
+        for path in &[
+            "a%2Fb",
+            "%2E%2E",
+            ".",
+            "rec%3Ffields%3Did",
+            "rec%23x",
+            "a%5Cb",
+        ] {
+            match segments("find", path) {
+                Err(Error::InvalidArgument { endpoint, arg }) => {
+                    assert_eq!(endpoint, "find");
+                    assert_eq!(&arg, path);
+                }
+                result => panic!("{} was {:?}", path, result),
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+//! The keys of the records in a response, from its `?keys=`, or the
+//! endpoint's `keys = ..;`, like `?keys=source`.
+//!
+//! They're the names of the fields of the `Mapped` types, unless they're
+//! `source`, the airtable columns the fields are read from, like the PHP proxy
+//! has them, `camelCase`, or `snake_case`, the fields' names in that case.
+//! Computed and `reverse` fields don't have a column, they keep their names
+//! with `source`.
+//!
+//! The records are renamed after their `?fields=` are selected, each by the
+//! `Rename` of its type, which goes into the records it links to.
+
+use crate::error::Error;
+use crate::expand::Link;
+use crate::field_types::{Collaborator, Date, DateTime, Decimal};
+use crate::gen_schema::Table;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Keys {
+    /// The names of the fields, as they're written in the schema.
+    #[default]
+    Fields,
+    /// The columns the fields are read from.
+    Source,
+    CamelCase,
+    SnakeCase,
+}
+
+impl FromStr for Keys {
+    type Err = Error;
+
+    fn from_str(keys: &str) -> Result<Self, Error> {
+        match keys {
+            "fields" => Ok(Keys::Fields),
+            "source" => Ok(Keys::Source),
+            "camelCase" => Ok(Keys::CamelCase),
+            "snake_case" => Ok(Keys::SnakeCase),
+            _ => Err(Error::InvalidQuery {
+                param: "keys".to_owned(),
+                value: keys.to_owned(),
+                expected: "one of: fields, source, camelCase, snake_case".to_owned(),
+            }),
+        }
+    }
+}
+
+impl Keys {
+    /// The key of the `field`, which is read from the `column`, when it has one.
+    pub fn key(self, field: &str, column: Option<&str>) -> String {
+        match (self, column) {
+            (Keys::Source, Some(column)) => column.to_owned(),
+            (Keys::Fields, _) | (Keys::Source, None) => field.to_owned(),
+            (Keys::CamelCase, _) => camel_case(field),
+            (Keys::SnakeCase, _) => snake_case(field),
+        }
+    }
+
+    /// Renames the keys of the records in the `value`, which is a `T`.
+    pub fn rename<T: Rename>(self, value: &mut Value) {
+        if self != Keys::Fields {
+            T::rename(value, self);
+        }
+    }
+
+    /// Renames the keys of the `object`, a record of `U`, keeping their order.
+    pub fn rename_fields<U: Table>(self, object: &mut Map<String, Value>) {
+        let column = |field: &str| {
+            U::COLUMNS
+                .iter()
+                .find(|(name, _)| *name == field)
+                .map(|(_, column)| *column)
+        };
+        *object = std::mem::take(object)
+            .into_iter()
+            .map(|(field, value)| (self.key(&field, column(&field)), value))
+            .collect();
+    }
+}
+
+/// Renames the records in the `field` of the `object`, which is a `T`.
+pub fn rename_field<T: Rename>(object: &mut Map<String, Value>, field: &str, keys: Keys) {
+    if let Some(value) = object.get_mut(field) {
+        T::rename(value, keys);
+    }
+}
+
+/// `due_date` is `dueDate`.
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    for (idx, word) in name.split('_').filter(|word| !word.is_empty()).enumerate() {
+        let mut chars = word.chars();
+        match chars.next() {
+            Some(first) if idx > 0 => camel.extend(first.to_uppercase()),
+            Some(first) => camel.push(first),
+            None => {}
+        }
+        camel.extend(chars);
+    }
+    camel
+}
+
+/// `dueDate` is `due_date`.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (idx, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if idx > 0 && !snake.ends_with('_') {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// A type whose JSON can have records in it, whose keys are renamed. The
+/// `Mapped` types rename their own, and the types of their fields, the ones
+/// of the records they link to.
+pub trait Rename {
+    fn rename(_value: &mut Value, _keys: Keys) {}
+}
+
+macro_rules! rename {
+    ($($type:ty),*) => {
+        $(impl Rename for $type {})*
+    };
+}
+
+rename!(String, &'static str, bool, f32, f64, Value, ());
+rename!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+rename!(Decimal, Date, DateTime, Collaborator);
+
+impl<T: Rename> Rename for Option<T> {
+    fn rename(value: &mut Value, keys: Keys) {
+        T::rename(value, keys);
+    }
+}
+
+impl<T: Rename> Rename for Vec<T> {
+    fn rename(value: &mut Value, keys: Keys) {
+        if let Value::Array(values) = value {
+            values.iter_mut().for_each(|value| T::rename(value, keys));
+        }
+    }
+}
+
+impl<T: Rename> Rename for HashMap<String, T> {
+    fn rename(value: &mut Value, keys: Keys) {
+        if let Value::Object(object) = value {
+            object.values_mut().for_each(|value| T::rename(value, keys));
+        }
+    }
+}
+
+impl<T: Rename> Rename for BTreeMap<String, T> {
+    fn rename(value: &mut Value, keys: Keys) {
+        if let Value::Object(object) = value {
+            object.values_mut().for_each(|value| T::rename(value, keys));
+        }
+    }
+}
+
+/// The IDs are left as they are, only the expanded records are renamed.
+impl<T: Rename> Rename for Link<T> {
+    fn rename(value: &mut Value, keys: Keys) {
+        T::rename(value, keys);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_from_str() {
+        assert_eq!("fields".parse::<Keys>().unwrap(), Keys::Fields);
+        assert_eq!("source".parse::<Keys>().unwrap(), Keys::Source);
+        assert_eq!("camelCase".parse::<Keys>().unwrap(), Keys::CamelCase);
+        assert_eq!("snake_case".parse::<Keys>().unwrap(), Keys::SnakeCase);
+        match "camel_case".parse::<Keys>() {
+            Err(Error::InvalidQuery { param, value, .. }) => {
+                assert_eq!(param, "keys");
+                assert_eq!(value, "camel_case");
+            }
+            result => panic!("camel_case was {:?}", result),
+        }
+    }
+
+    #[test]
+    fn camel_case_keys() {
+        assert_eq!(camel_case("due_date"), "dueDate");
+        assert_eq!(camel_case("was_paid_on_time"), "wasPaidOnTime");
+        assert_eq!(camel_case("number"), "number");
+        assert_eq!(camel_case("_double__underscore_"), "doubleUnderscore");
+    }
+
+    #[test]
+    fn snake_case_keys() {
+        assert_eq!(snake_case("dueDate"), "due_date");
+        assert_eq!(snake_case("due_date"), "due_date");
+        assert_eq!(snake_case("DueDate"), "due_date");
+        assert_eq!(snake_case("number"), "number");
+    }
+
+    #[test]
+    fn key_of_a_field() {
+        assert_eq!(Keys::Fields.key("due_date", Some("Due Date")), "due_date");
+        assert_eq!(Keys::Source.key("due_date", Some("Due Date")), "Due Date");
+        assert_eq!(Keys::Source.key("subtotal", None), "subtotal");
+        assert_eq!(Keys::CamelCase.key("due_date", Some("Due Date")), "dueDate");
+    }
+}
@@ -1,59 +1,166 @@
 pub use serde;
 pub use serde::*;
+pub use serde_json;
+pub use serde_urlencoded;
 pub use tokio;
 pub use warp;
 
 pub use log::{debug, info, trace, warn};
 pub use pretty_env_logger;
 
+pub use airtable_proxy_derive::AirtableTable;
+
 pub mod airtable;
+pub mod batch;
+pub mod builder;
+pub mod cli;
 pub mod config;
 pub mod ctx;
 pub mod error;
+pub mod expand;
+pub mod field_types;
 pub mod gen_schema;
+pub mod keys;
+pub mod list;
 pub mod network;
+pub mod openapi;
+pub mod raw;
+pub mod reverse;
+pub mod secret;
+pub mod shape;
+pub mod shutdown;
 pub mod transform;
+pub mod webhook;
 
+/// Generates the `main` of the proxy binary, for one schema at the root,
+/// `start_proxy!(schema)`, or for several, each in a namespace mounted under
+/// its own prefix, `start_proxy!(invoices => invoice_schema, timesheets => timesheet_schema)`.
 #[macro_export]
 macro_rules! start_proxy {
     ($namespace:ident) => {
+        $crate::start_proxy!(@main [ (None, $namespace) ]);
+    };
+    ($($name:ident => $namespace:ident),+ $(,)?) => {
+        $crate::start_proxy!(@main [ $((Some(std::stringify!($name)), $namespace)),+ ]);
+    };
+    (@main [ $(($name:expr, $namespace:ident)),+ ]) => {
         #[tokio::main]
         async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-            pretty_env_logger::init();
-            $crate::start_proxy!(@inner $namespace)
+            use $crate::cli::Command;
+            use $crate::config::Config;
+
+            const NAMESPACES: $crate::cli::Namespaces = &[
+                $( ($name, $namespace::gen::SOURCES, $namespace::gen::ENDPOINTS) ),+
+            ];
+
+            match Command::parse(NAMESPACES) {
+                Command::Check => $crate::cli::check(Config::load(), NAMESPACES),
+                Command::Types { format, namespace, path } => {
+                    let components = std::vec![ $( ($name, $namespace::gen::components()) ),+ ];
+                    $crate::cli::types(components, format, namespace, path)
+                }
+                Command::Get { namespace, module, endpoint, arg, query, body } => {
+                    let config = $crate::cli::load_config(None, None, None)?;
+                    let namespace = $crate::cli::namespace_of(NAMESPACES, namespace.as_deref(), &module)?;
+                    $(if namespace == $name {
+                        let config = config.namespace($name)?;
+                        config.validate_sources($namespace::gen::SOURCES)?;
+                        let mut ctx = $crate::airtable::FetchCtx::new(config)?;
+                        ctx.set_scope(query.scope()?);
+                        let value = $namespace::gen::get(&mut ctx, &module, &endpoint, &arg, body.as_deref()).await?;
+                        return $crate::cli::print(&value);
+                    })+
+                    Err($crate::error::Error::UnknownEndpoint { module, endpoint }.into())
+                }
+                Command::CacheDump { namespace, path } => {
+                    let config = $crate::cli::load_config(None, None, None)?;
+                    $crate::cli::cache_dump(&config, namespace, path)
+                }
+                Command::CacheLoad { namespace, path } => {
+                    let config = $crate::cli::load_config(None, None, None)?;
+                    $crate::cli::cache_load(&config, namespace, path)
+                }
+                Command::Serve { host, port, log } => {
+                    let config = $crate::cli::load_config(host, port, log)?;
+                    let mut builder = $crate::builder::ProxyBuilder::new(config.clone());
+                    $(builder = match $name {
+                        Some(name) => builder.namespace::<$namespace::gen::Schema>(name),
+                        None => builder.schema::<$namespace::gen::Schema>(),
+                    };)+
+                    $crate::start_proxy!(@inner builder, config)
+                }
+            }
         }
     };
-    (@inner $namespace:ident) => {{
+    (@inner $builder:expr, $config:expr) => {{
         info!("Attempting to start the proxy.");
-        //
-        // make sure we can airtable, yo, that we have the
-        // right env setup/permissions here.
-        let ctx = $crate::airtable::FetchCtx::from_env()?;
-        debug!("FetchCtx constructed.");
+        let config: $crate::config::Config = $config;
 
         //
-        // get our server port from the environment, default is 3000
-        let address: ::std::net::SocketAddr = ::std::env::var("HOST")
-            .unwrap_or_else(|_| "127.0.0.1:3000".to_string())
-            .parse()?;
+        // get our server address from the config, default is 127.0.0.1:3000
+        let address: ::std::net::SocketAddr = config.server.host.parse()?;
         debug!("parsed out address={}", address);
+        let shutdown_timeout = config.shutdown_timeout();
 
         //
-        // make our context async ready
-        let ctx = $crate::ctx::wrap_ctx(ctx);
-
+        // make sure we can airtable, yo, that we have the right
+        // config/permissions for every table in the schema, and
+        // grab the generated router, and what its routes are
+        let builder: $crate::builder::ProxyBuilder = $builder;
+        let proxy = builder.build()?;
+        for mounted in &proxy.mounted {
+            info!(
+                "Mounted namespace={} at /{}",
+                mounted.namespace.unwrap_or("(root)"),
+                mounted.prefix
+            );
+        }
+        for route in &proxy.routes {
+            match (route.table, route.input) {
+                (Some(table), Some(input)) => info!(
+                    "Route {} {} table={} input={} exec=[{}]",
+                    route.method,
+                    route.path,
+                    table,
+                    input,
+                    route.exec.unwrap_or_default()
+                ),
+                _ => info!("Route {} {}", route.method, route.path),
+            }
+        }
+        let ctxs: Vec<$crate::ctx::Ctx> = proxy.ctxs().cloned().collect();
         //
-        // grab the generated router
+        // anything that isn't a route gets a JSON 404 listing them
+        let router = $crate::builder::with_not_found(proxy.route, proxy.routes);
+        debug!("FetchCtx constructed.");
+
         //
-        // todo: this should print/log the routes, which means
-        // that `route` methods should probably return a tuple
-        // of the `warp::filter` with some `debug` struct that we
-        // can output here.
-        let router = $namespace::gen::route(ctx);
+        // rotate keys without restarting with `kill -HUP`
+        #[cfg(unix)]
+        $crate::tokio::spawn($crate::ctx::reload_secrets_on_hangup(ctxs.clone()));
 
+        //
+        // serve until we get SIGTERM/SIGINT, then stop accepting connections,
+        // and give the in-flight requests some time to finish.
+        let (stop, stopped) = $crate::tokio::sync::oneshot::channel::<()>();
+        let (address, server) = $crate::warp::serve(router)
+            .bind_with_graceful_shutdown(address, async {
+                stopped.await.ok();
+            });
         info!("Starting proxy at address={}", address);
-        $crate::warp::serve(router).run(address).await;
+        let server = $crate::tokio::spawn(server);
+
+        $crate::shutdown::signal().await;
+        info!("Shutting down, waiting up to {:?} for in-flight requests.", shutdown_timeout);
+        stop.send(()).ok();
+        if $crate::tokio::time::timeout(shutdown_timeout, server).await.is_err() {
+            warn!("In-flight requests didn't finish in time, dropping them.");
+        }
 
+        for ctx in ctxs {
+            $crate::shutdown::flush(ctx, shutdown_timeout).await;
+        }
+        info!("Stopped.");
         Ok(())
     }};
 }
@@ -0,0 +1,478 @@
+//! Listing the records of a table, the `GET /{module}` every table gets.
+//!
+//! Fields marked `filter;` can be filtered on with `?field=value`, which
+//! becomes an airtable formula, or for `link;` fields, keeps the records
+//! linked to that ID. `?sort=field` (or `-field`, descending) sorts by one of
+//! the table's fields, and `?page_size=` and `?cursor=` page through them, the
+//! cursor is the `offset` airtable gives back for the next page.
+//!
+//! Airtable can't filter on links, so with a `link;` filter, its pages are
+//! fetched until there are `page_size` records linked to the ID, and the
+//! cursor is the `offset` of the last one, and how many of its records were
+//! already looked at. So a request doesn't read the whole table, at most
+//! `MAX_LINK_PAGES` are fetched for one, which can leave it with fewer
+//! records than `page_size`, but still a cursor for the rest.
+
+use crate::airtable::FetchCtx;
+use crate::error::Error;
+use crate::field_types::{Date, Decimal};
+use crate::gen_schema::Table;
+use crate::network::response::{List, One};
+use crate::openapi::{Operation, Segment};
+use crate::shape::{Describe, Shape};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The query parameters of a list that aren't filters.
+const RESERVED: &[&str] = &["expand", "fields", "keys", "sort", "page_size", "cursor"];
+
+/// Airtable doesn't give back more than this many records at once.
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// The most pages of airtable's fetched for one page with a `link;` filter.
+const MAX_LINK_PAGES: usize = 10;
+
+/// How the value of a `filter;` field in the query string is compared to
+/// its `column`, in an airtable formula, or `Err` with what was expected.
+pub trait Filter {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str>;
+}
+
+/// A string for a formula, in quotes, with the quotes in it escaped.
+pub(crate) fn quoted(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+impl Filter for String {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+        Ok(format!("{{{}}} = {}", column, quoted(value)))
+    }
+}
+
+impl Filter for bool {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+        match value {
+            "true" => Ok(format!("{{{}}}", column)),
+            "false" => Ok(format!("NOT({{{}}})", column)),
+            _ => Err("true or false"),
+        }
+    }
+}
+
+impl Filter for Date {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+        match value.parse::<Date>() {
+            Ok(date) => Ok(format!("IS_SAME({{{}}}, '{}', 'day')", column, date)),
+            Err(_) => Err("a date, like 2020-01-31"),
+        }
+    }
+}
+
+/// Multiple selects and lookups, it has the value.
+impl Filter for Vec<String> {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+        Ok(format!(
+            "FIND({}, ARRAYJOIN({{{}}}, ','))",
+            quoted(value),
+            column
+        ))
+    }
+}
+
+impl<T: Filter> Filter for Option<T> {
+    fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+        T::formula(column, value)
+    }
+}
+
+macro_rules! number_filter {
+    ($($type:ty),*) => {
+        $(impl Filter for $type {
+            fn formula(column: &str, value: &str) -> std::result::Result<String, &'static str> {
+                match value.parse::<$type>() {
+                    Ok(number) => Ok(format!("{{{}}} = {}", column, number)),
+                    Err(_) => Err("a number"),
+                }
+            }
+        })*
+    };
+}
+
+number_filter!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, Decimal);
+
+/// The filters of a list, from the query, each field's by `filter` in the
+/// generated `filters`.
+#[derive(Debug, Default)]
+pub struct Filters {
+    pub formulas: Vec<String>,
+    /// Pairs of `(column, record ID)`, for the `link;` fields.
+    pub links: Vec<(&'static str, String)>,
+    /// The names of the fields that can be filtered on, and their shapes.
+    pub fields: Vec<(&'static str, Shape)>,
+}
+
+impl Filters {
+    /// Adds the filter on the field `name` in the `column`, if the query has it.
+    pub fn filter<T: Filter + Describe>(
+        &mut self,
+        query: &HashMap<String, String>,
+        name: &'static str,
+        column: &'static str,
+    ) -> Result<()> {
+        self.fields.push((name, T::shape()));
+        if let Some(value) = query.get(name) {
+            let formula = T::formula(column, value).map_err(|expected| Error::InvalidQuery {
+                param: name.to_owned(),
+                value: value.clone(),
+                expected: expected.to_owned(),
+            })?;
+            self.formulas.push(formula);
+        }
+        Ok(())
+    }
+
+    /// Adds the filter on the `link;` field `name` in the `column`, if the query has it.
+    pub fn link(
+        &mut self,
+        query: &HashMap<String, String>,
+        name: &'static str,
+        column: &'static str,
+    ) {
+        self.fields.push((name, Shape::String(None)));
+        if let Some(id) = query.get(name) {
+            self.links.push((column, id.clone()));
+        }
+    }
+
+    /// Errors with the first of the `query`'s parameters that isn't a filter.
+    fn check(&self, query: &HashMap<String, String>) -> Result<()> {
+        let is_filter = |key: &str| self.fields.iter().any(|(name, _)| *name == key);
+        let unknown = query
+            .iter()
+            .find(|(key, _)| !RESERVED.contains(&key.as_str()) && !is_filter(key));
+        match unknown {
+            Some((key, value)) => Err(Error::InvalidQuery {
+                param: key.clone(),
+                value: value.clone(),
+                expected: format!(
+                    "a filter, one of: {}",
+                    self.fields
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// All of the formulas, as one.
+    fn formula(&self) -> Option<String> {
+        match self.formulas.len() {
+            0 => None,
+            1 => Some(self.formulas[0].clone()),
+            _ => Some(format!("AND({})", self.formulas.join(", "))),
+        }
+    }
+}
+
+/// A request for a page of a table, to airtable.
+#[derive(Debug, Default)]
+pub struct ListParams {
+    pub formula: Option<String>,
+    /// The column, and whether it's descending.
+    pub sort: Option<(&'static str, bool)>,
+    pub page_size: Option<u32>,
+    pub offset: Option<String>,
+}
+
+impl ListParams {
+    /// The query string parameters airtable takes, for these.
+    pub(crate) fn pairs(&self) -> Vec<(&'static str, String)> {
+        let mut pairs = vec![];
+        if let Some(formula) = &self.formula {
+            pairs.push(("filterByFormula", formula.clone()));
+        }
+        if let Some((column, descending)) = self.sort {
+            pairs.push(("sort[0][field]", column.to_owned()));
+            let direction = if descending { "desc" } else { "asc" };
+            pairs.push(("sort[0][direction]", direction.to_owned()));
+        }
+        if let Some(page_size) = self.page_size {
+            pairs.push(("pageSize", page_size.to_string()));
+        }
+        if let Some(offset) = &self.offset {
+            pairs.push(("offset", offset.clone()));
+        }
+        pairs
+    }
+}
+
+/// One page of a list from airtable, `offset` is where the next one starts.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Page {
+    pub records: Vec<One<Map<String, Value>>>,
+    #[serde(default)]
+    pub offset: Option<String>,
+}
+
+/// Whether the `record` links to the record `id` in its `column`, airtable's
+/// formulas see the names of linked records, not their IDs, so it's checked
+/// here instead.
+pub(crate) fn links_to(record: &One<Map<String, Value>>, column: &str, id: &str) -> bool {
+    match record.fields.get(column) {
+        Some(Value::Array(ids)) => ids.iter().any(|linked| linked.as_str() == Some(id)),
+        _ => false,
+    }
+}
+
+/// The `Fields` of a `record` of a page.
+pub(crate) fn fields<T: DeserializeOwned>(record: One<Map<String, Value>>) -> Result<One<T>> {
+    Ok(One {
+        id: record.id,
+        fields: serde_json::from_value(Value::Object(record.fields))
+            .map_err(Error::SerdeTransform)?,
+        created_time: record.created_time,
+    })
+}
+
+/// Where the next page starts, airtable's `offset` of the page it's in, and
+/// how many of that page's records come before it.
+#[derive(Debug, Default)]
+struct Cursor {
+    offset: Option<String>,
+    skip: usize,
+}
+
+/// The cursor is opaque to clients, it's the `skip` and `offset` in base64.
+fn encode_cursor(cursor: &Cursor) -> String {
+    let cursor = format!("{}:{}", cursor.skip, cursor.offset.as_deref().unwrap_or(""));
+    base64::encode_config(cursor, base64::URL_SAFE_NO_PAD)
+}
+
+fn decode_cursor(cursor: &str) -> Result<Cursor> {
+    base64::decode_config(cursor, base64::URL_SAFE_NO_PAD)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .and_then(|decoded| {
+            let (skip, offset) = decoded.split_once(':')?;
+            Some(Cursor {
+                offset: Some(offset.to_owned()).filter(|offset| !offset.is_empty()),
+                skip: skip.parse().ok()?,
+            })
+        })
+        .ok_or_else(|| Error::InvalidQuery {
+            param: "cursor".to_owned(),
+            value: cursor.to_owned(),
+            expected: "the cursor of a previous page".to_owned(),
+        })
+}
+
+/// Fetches a page of the records of `U`, filtered, sorted, and paged by the
+/// `query`, with the `filters` of its fields, returning them, and the cursor
+/// of the next page, if there is one.
+pub async fn list<U: Table>(
+    ctx: &mut FetchCtx,
+    query: &HashMap<String, String>,
+    filters: Filters,
+) -> Result<(List<U::Fields>, Option<String>)> {
+    filters.check(query)?;
+    ctx.scope().check(U::NAME, U::FIELD_NAMES)?;
+    let sort = match query.get("sort") {
+        Some(sort) => {
+            let (name, descending) = match sort.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (sort.as_str(), false),
+            };
+            match U::COLUMNS.iter().find(|(field, _)| *field == name) {
+                Some((_, column)) => Some((*column, descending)),
+                None => {
+                    return Err(Error::InvalidQuery {
+                        param: "sort".to_owned(),
+                        value: sort.clone(),
+                        expected: format!(
+                            "one of the fields, or -field to sort descending: {}",
+                            U::COLUMNS
+                                .iter()
+                                .map(|(field, _)| *field)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    })
+                }
+            }
+        }
+        None => None,
+    };
+    let page_size = match query.get("page_size") {
+        Some(size) => match size.parse::<u32>() {
+            Ok(size) if size > 0 && size <= MAX_PAGE_SIZE => Some(size),
+            _ => {
+                return Err(Error::InvalidQuery {
+                    param: "page_size".to_owned(),
+                    value: size.clone(),
+                    expected: format!("a number from 1 to {}", MAX_PAGE_SIZE),
+                })
+            }
+        },
+        None => None,
+    };
+    let cursor = query
+        .get("cursor")
+        .map(|cursor| decode_cursor(cursor))
+        .transpose()?
+        .unwrap_or_default();
+    let mut params = ListParams {
+        formula: filters.formula(),
+        sort,
+        page_size,
+        offset: cursor.offset,
+    };
+
+    if filters.links.is_empty() {
+        let page: Page = ctx.fetch_list(U::SOURCE, &params).await?;
+        let records = page
+            .records
+            .into_iter()
+            .map(fields)
+            .collect::<Result<List<U::Fields>>>()?;
+        let next = page.offset.map(|offset| Cursor {
+            offset: Some(offset),
+            skip: 0,
+        });
+        return Ok((records, next.as_ref().map(encode_cursor)));
+    }
+
+    // the pages are always as big as they can be, so the `skip` of a cursor
+    // lands on the same record when its page is fetched again
+    let wanted = page_size.unwrap_or(MAX_PAGE_SIZE) as usize;
+    params.page_size = Some(MAX_PAGE_SIZE);
+    let mut skip = cursor.skip;
+    let mut records = vec![];
+    let mut fetched = 0;
+    loop {
+        let page: Page = ctx.fetch_list(U::SOURCE, &params).await?;
+        fetched += 1;
+        for (idx, record) in page.records.into_iter().enumerate().skip(skip) {
+            if records.len() == wanted {
+                let next = Cursor {
+                    offset: params.offset,
+                    skip: idx,
+                };
+                return Ok((records, Some(encode_cursor(&next))));
+            }
+            if filters
+                .links
+                .iter()
+                .all(|(column, id)| links_to(&record, column, id))
+            {
+                records.push(fields(record)?);
+            }
+        }
+        params.offset = match page.offset {
+            Some(offset) => Some(offset),
+            None => return Ok((records, None)),
+        };
+        skip = 0;
+        if records.len() == wanted || fetched == MAX_LINK_PAGES {
+            let next = Cursor {
+                offset: params.offset,
+                skip,
+            };
+            return Ok((records, Some(encode_cursor(&next))));
+        }
+    }
+}
+
+/// The list of `U`, for the spec, with the `filters` of its fields.
+pub fn operation<U: Table + Describe>(filters: Filters) -> Operation {
+    let mut query = filters.fields;
+    query.push(("sort", Shape::String(None)));
+    query.push(("page_size", Shape::Integer));
+    query.push(("cursor", Shape::String(None)));
+    let response = Shape::Object(vec![
+        ("records", Vec::<U>::shape()),
+        ("cursor", Option::<String>::shape()),
+    ]);
+    Operation {
+        query,
+        input: Some("HashMap<String, String>"),
+        exec: Some("list::list, create_many"),
+        ..Operation::endpoint::<U>("list", vec![Segment::Literal(U::MODULE_NAME)], response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cursors_round_trip() {
+        for cursor in &[
+            Cursor {
+                offset: Some("itrABC/recXYZ".to_owned()),
+                skip: 0,
+            },
+            Cursor {
+                offset: Some("itr:with:colons".to_owned()),
+                skip: 42,
+            },
+            Cursor {
+                offset: None,
+                skip: 7,
+            },
+        ] {
+            let decoded = decode_cursor(&encode_cursor(cursor)).unwrap();
+            assert_eq!(decoded.offset, cursor.offset);
+            assert_eq!(decoded.skip, cursor.skip);
+        }
+    }
+
+    #[test]
+    fn other_cursors_are_invalid() {
+        let not_a_number = base64::encode_config("x:itrABC", base64::URL_SAFE_NO_PAD);
+        let no_colon = base64::encode_config("itrABC", base64::URL_SAFE_NO_PAD);
+        for cursor in &["not base64!", not_a_number.as_str(), no_colon.as_str()] {
+            match decode_cursor(cursor) {
+                Err(Error::InvalidQuery { param, .. }) => assert_eq!(param, "cursor"),
+                result => panic!("{} was {:?}", cursor, result),
+            }
+        }
+    }
+
+    #[test]
+    fn formulas() {
+        assert_eq!(
+            String::formula("Name", "it's").unwrap(),
+            r"{Name} = 'it\'s'"
+        );
+        assert_eq!(bool::formula("Paid?", "false").unwrap(), "NOT({Paid?})");
+        assert!(bool::formula("Paid?", "no").is_err());
+        assert_eq!(
+            Date::formula("Date", "2020-01-31").unwrap(),
+            "IS_SAME({Date}, '2020-01-31', 'day')"
+        );
+        assert_eq!(u32::formula("ID", "12").unwrap(), "{ID} = 12");
+        assert!(u32::formula("ID", "12 OR 1").is_err());
+    }
+
+    #[test]
+    fn links() {
+        let record: One<Map<String, Value>> = serde_json::from_value(json!({
+            "id": "recINVOICE0000001",
+            "fields": { "Client": ["recCLIENT00000001"], "Name": "x" },
+            "createdTime": "2020-01-31T00:00:00.000Z",
+        }))
+        .unwrap();
+        assert!(links_to(&record, "Client", "recCLIENT00000001"));
+        assert!(!links_to(&record, "Client", "recCLIENT00000002"));
+        assert!(!links_to(&record, "Name", "x"));
+        assert!(!links_to(&record, "Items", "recCLIENT00000001"));
+    }
+}
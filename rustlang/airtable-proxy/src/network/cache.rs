@@ -1,8 +1,11 @@
 use crate::error::Error;
 use reqwest::Url;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::future::Future;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use warp::hyper::body::{Body, Bytes};
 
 pub(crate) type JSONResult = Result<Value, Error>;
 
@@ -12,17 +15,102 @@ pub(crate) struct Stats {
     pub misses: u32,
 }
 
+/// The bytes of a downloaded attachment, along with its content type.
+#[derive(Debug, Clone)]
+pub(crate) struct File {
+    pub content_type: String,
+    pub bytes: Bytes,
+}
+
+/// A downloaded attachment, the whole `File` when it's small enough to cache,
+/// or the `body` of a bigger one, streamed as it's downloaded.
+#[derive(Debug)]
+pub(crate) enum Download {
+    File(File),
+    Stream { content_type: String, body: Body },
+}
+
+pub(crate) type FileResult = Result<Download, Error>;
+
+/// The cached responses, in a shape we can save to, and load from, a file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub responses: Vec<SnapshotEntry>,
+    /// The cursor of each webhook's payloads, so they're not all read again
+    /// after a restart, see `webhook`.
+    #[serde(default)]
+    pub webhook_cursors: HashMap<String, u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SnapshotEntry {
+    pub url: String,
+    pub value: Value,
+    /// When the response was fetched, in seconds since the unix epoch, so
+    /// the time it spends in the file counts towards its `ttl`.
+    #[serde(default)]
+    pub fetched_at: u64,
+}
+
+/// The responses fetched during a batch, which are reused for all of its
+/// keys, see `FetchCtx::batch`.
+#[derive(Debug, Default)]
+pub struct Batch(HashMap<Url, Value>);
+
+#[derive(Debug)]
+struct Entry {
+    value: Value,
+    inserted: Instant,
+}
+
 #[derive(Debug)]
 pub(crate) struct Cache {
+    enabled: bool,
+    ttl: Option<Duration>,
     stats: Stats,
-    storage: HashMap<Url, Value>,
+    storage: HashMap<Url, Entry>,
+    files: HashMap<String, File>,
+    /// The ids of the cached `files`, oldest first, so those are the ones
+    /// dropped to make room for a new one.
+    file_ids: VecDeque<String>,
+    /// The size of all of the cached `files` together.
+    files_bytes: u64,
+    /// Files bigger than this are served, but not cached.
+    max_file_bytes: u64,
+    /// The most `files_bytes` can be.
+    max_files_bytes: u64,
+    /// The responses fetched during a batch, which are reused until it ends,
+    /// even when the cache isn't `enabled`, or they've expired.
+    batch: Option<Batch>,
 }
 
 impl Cache {
-    pub(crate) fn new() -> Self {
+    /// When the cache isn't `enabled` every request is a miss, and when
+    /// there's a `ttl`, responses older than that are fetched again.
+    pub(crate) fn new(
+        enabled: bool,
+        ttl: Option<Duration>,
+        max_file_bytes: u64,
+        max_files_bytes: u64,
+    ) -> Self {
         Self {
+            enabled,
+            ttl,
             stats: Stats { hits: 0, misses: 0 },
             storage: HashMap::new(),
+            files: HashMap::new(),
+            file_ids: VecDeque::new(),
+            files_bytes: 0,
+            max_file_bytes,
+            max_files_bytes,
+            batch: None,
+        }
+    }
+
+    fn is_fresh(&self, entry: &Entry) -> bool {
+        match self.ttl {
+            Some(ttl) => entry.inserted.elapsed() < ttl,
+            None => true,
         }
     }
 
@@ -30,10 +118,28 @@ impl Cache {
         &self.stats
     }
 
+    /// Starts a batch, until `end_batch` each url is fetched at most once.
+    pub(crate) fn start_batch(&mut self, batch: Batch) {
+        self.batch = Some(batch);
+    }
+
+    /// Ends the batch, returning the responses fetched in it.
+    pub(crate) fn end_batch(&mut self) -> Batch {
+        self.batch.take().unwrap_or_default()
+    }
+
     pub(crate) fn clear(&mut self) {
         self.stats.hits = 0;
         self.stats.misses = 0;
         self.storage.clear();
+        self.files.clear();
+        self.file_ids.clear();
+        self.files_bytes = 0;
+    }
+
+    /// Removes the response for the `url`, so it's fetched again.
+    pub(crate) fn remove(&mut self, url: &Url) {
+        self.storage.remove(url);
     }
 
     pub(crate) async fn get_or_insert_with<G: Future<Output = JSONResult>, F: FnOnce(Url) -> G>(
@@ -41,16 +147,298 @@ impl Cache {
         url: Url,
         f: F,
     ) -> JSONResult {
-        Ok(if let Some(value) = self.storage.get(&url) {
+        let cached = self
+            .storage
+            .get(&url)
+            .filter(|entry| self.enabled && self.is_fresh(entry))
+            .map(|entry| entry.value.clone())
+            .or_else(|| {
+                self.batch
+                    .as_ref()
+                    .and_then(|batch| batch.0.get(&url).cloned())
+            });
+        Ok(if let Some(value) = cached {
             crate::debug!("hit | url={}", url);
             self.stats.hits += 1;
-            value.clone()
+            value
         } else {
             crate::debug!("miss | url={}", url);
             self.stats.misses += 1;
             let value = f(url.clone()).await?;
-            self.storage.insert(url, value.clone());
+            if let Some(batch) = &mut self.batch {
+                batch.0.insert(url.clone(), value.clone());
+            }
+            if self.enabled {
+                let entry = Entry {
+                    value: value.clone(),
+                    inserted: Instant::now(),
+                };
+                self.storage.insert(url, entry);
+            }
             value
         })
     }
+
+    /// Attachments are keyed by their airtable attachment `id` and not
+    /// their url, since the urls expire and the file behind an id does not.
+    pub(crate) async fn get_or_insert_file_with<
+        G: Future<Output = FileResult>,
+        F: FnOnce() -> G,
+    >(
+        &mut self,
+        id: String,
+        f: F,
+    ) -> FileResult {
+        Ok(if let Some(file) = self.files.get(&id) {
+            crate::debug!("hit | attachment={}", id);
+            self.stats.hits += 1;
+            Download::File(file.clone())
+        } else {
+            crate::debug!("miss | attachment={}", id);
+            self.stats.misses += 1;
+            let download = f().await?;
+            if let Download::File(file) = &download {
+                self.insert_file(id, file.clone());
+            }
+            download
+        })
+    }
+
+    /// Caches the `file`, dropping the oldest ones until it fits in the
+    /// `max_files_bytes`.
+    fn insert_file(&mut self, id: String, file: File) {
+        let len = file.bytes.len() as u64;
+        if !self.enabled || len > self.max_file_bytes || len > self.max_files_bytes {
+            return;
+        }
+        while self.files_bytes + len > self.max_files_bytes {
+            let oldest = match self.file_ids.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some(dropped) = self.files.remove(&oldest) {
+                crate::debug!("dropped | attachment={}", oldest);
+                self.files_bytes -= dropped.bytes.len() as u64;
+            }
+        }
+        self.files_bytes += len;
+        self.file_ids.push_back(id.clone());
+        self.files.insert(id, file);
+    }
+
+    /// Removes every cached response that could be stale after the records
+    /// with `ids` in the `base` changed, or were created, returning how many
+    /// were removed.
+    ///
+    /// Those are the single record lookups for the `ids`, and every list and
+    /// query of the base's tables, since a changed record can start, or stop,
+    /// matching any of their formulas. The webhook's payloads name the tables
+    /// by their IDs, and the urls by their names, so it's all of the base's.
+    pub(crate) fn invalidate(&mut self, base: &str, ids: &HashSet<String>) -> usize {
+        let before = self.storage.len();
+        self.storage.retain(|url, _| {
+            let segments: Vec<&str> = url
+                .path_segments()
+                .map(|segments| segments.collect())
+                .unwrap_or_default();
+            match segments.iter().position(|segment| *segment == base) {
+                // a record, `{base}/{table}/{id}`
+                Some(at) if segments.len() == at + 3 => !ids.contains(segments[at + 2]),
+                // a list or query, `{base}/{table}`
+                Some(_) => false,
+                None => true,
+            }
+        });
+        let removed = before - self.storage.len();
+        crate::debug!(
+            "invalidate | base={} ids={:?} removed={}",
+            base,
+            ids,
+            removed
+        );
+        removed
+    }
+
+    /// The responses that are still fresh, attachments aren't included.
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            webhook_cursors: HashMap::new(),
+            responses: self
+                .storage
+                .iter()
+                .filter(|(_, entry)| self.is_fresh(entry))
+                .map(|(url, entry)| SnapshotEntry {
+                    url: url.to_string(),
+                    value: entry.value.clone(),
+                    fetched_at: (SystemTime::now() - entry.inserted.elapsed())
+                        .duration_since(UNIX_EPOCH)
+                        .map(|since| since.as_secs())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds the responses in the `snapshot` to the cache, as old as they are,
+    /// returning how many were added. Ones older than the `ttl` are skipped,
+    /// and none are added when the cache isn't `enabled`.
+    pub(crate) fn restore(&mut self, snapshot: Snapshot) -> usize {
+        if !self.enabled {
+            return 0;
+        }
+        let mut restored = 0;
+        for SnapshotEntry {
+            url,
+            value,
+            fetched_at,
+        } in snapshot.responses
+        {
+            let age = SystemTime::now()
+                .duration_since(UNIX_EPOCH + Duration::from_secs(fetched_at))
+                .unwrap_or_default();
+            if self.ttl.is_some_and(|ttl| age >= ttl) {
+                continue;
+            }
+            // an `Instant` can't always be that far back, like soon after a
+            // boot, then it's only kept when there's no ttl it could outlive
+            let inserted = match Instant::now().checked_sub(age) {
+                Some(inserted) => inserted,
+                None if self.ttl.is_none() => Instant::now(),
+                None => {
+                    crate::warn!("restore | skipping url={}, fetched too long ago", url);
+                    continue;
+                }
+            };
+            match Url::parse(&url) {
+                Ok(url) => {
+                    let entry = Entry { value, inserted };
+                    self.storage.insert(url, entry);
+                    restored += 1;
+                }
+                Err(_) => crate::warn!("restore | skipping invalid url={}", url),
+            }
+        }
+        restored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const BASE: &str = "appBASE0000000001";
+
+    fn url(path: &str) -> String {
+        format!("https://api.airtable.com/v0/{}", path)
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    /// A snapshot of the `urls`, each fetched `age` seconds ago.
+    fn snapshot(urls: &[String], age: u64) -> Snapshot {
+        Snapshot {
+            responses: urls
+                .iter()
+                .map(|url| SnapshotEntry {
+                    url: url.clone(),
+                    value: json!({ "url": url }),
+                    fetched_at: now() - age,
+                })
+                .collect(),
+            webhook_cursors: HashMap::new(),
+        }
+    }
+
+    fn urls(cache: &Cache) -> Vec<String> {
+        let mut urls: Vec<String> = cache
+            .snapshot()
+            .responses
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect();
+        urls.sort();
+        urls
+    }
+
+    #[test]
+    fn invalidate_removes_the_records_and_lists_of_the_base() {
+        let mut cache = Cache::new(true, None, 0, 0);
+        let cached = [
+            url(&format!("{}/Invoice/recCHANGED0000001", BASE)),
+            url(&format!("{}/Invoice/recSAME0000000001", BASE)),
+            url(&format!("{}/Invoice?pageSize=10", BASE)),
+            url(&format!("{}/Clients", BASE)),
+            url("appOTHER00000001/Invoice/recCHANGED0000001"),
+        ];
+        assert_eq!(cache.restore(snapshot(&cached, 0)), 5);
+        let ids = vec!["recCHANGED0000001".to_owned()].into_iter().collect();
+        assert_eq!(cache.invalidate(BASE, &ids), 3);
+        assert_eq!(urls(&cache), vec![cached[1].clone(), cached[4].clone()]);
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let mut cache = Cache::new(true, Some(Duration::from_secs(60)), 0, 0);
+        let cached = [url("a"), url("b")];
+        cache.restore(snapshot(&cached, 10));
+        let saved = cache.snapshot();
+        assert!(saved
+            .responses
+            .iter()
+            .all(|entry| entry.fetched_at <= now() - 9 && entry.fetched_at >= now() - 11));
+
+        let mut restored = Cache::new(true, Some(Duration::from_secs(60)), 0, 0);
+        assert_eq!(restored.restore(saved), 2);
+        assert_eq!(urls(&restored), cached);
+    }
+
+    #[test]
+    fn restore_skips_expired_responses() {
+        let mut cache = Cache::new(true, Some(Duration::from_secs(60)), 0, 0);
+        assert_eq!(cache.restore(snapshot(&[url("old")], 61)), 0);
+        assert_eq!(cache.restore(snapshot(&[url("new")], 59)), 1);
+        assert_eq!(urls(&cache), vec![url("new")]);
+
+        let mut forever = Cache::new(true, None, 0, 0);
+        assert_eq!(forever.restore(snapshot(&[url("old")], 1_000_000)), 1);
+    }
+
+    #[test]
+    fn a_disabled_cache_restores_nothing() {
+        let mut cache = Cache::new(false, None, 0, 0);
+        assert_eq!(cache.restore(snapshot(&[url("a")], 0)), 0);
+        assert!(cache.snapshot().responses.is_empty());
+    }
+
+    fn file(len: usize) -> File {
+        File {
+            content_type: "text/plain".to_owned(),
+            bytes: Bytes::from(vec![b'x'; len]),
+        }
+    }
+
+    fn file_ids(cache: &Cache) -> Vec<&str> {
+        cache.file_ids.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn the_oldest_files_are_dropped_past_the_budget() {
+        let mut cache = Cache::new(true, None, 4, 10);
+        cache.insert_file("a".to_owned(), file(4));
+        cache.insert_file("b".to_owned(), file(4));
+        cache.insert_file("too big".to_owned(), file(5));
+        assert_eq!(file_ids(&cache), ["a", "b"]);
+        cache.insert_file("c".to_owned(), file(3));
+        assert_eq!(file_ids(&cache), ["b", "c"]);
+        assert_eq!(cache.files_bytes, 7);
+        assert!(!cache.files.contains_key("a"));
+        cache.clear();
+        assert_eq!(cache.files_bytes, 0);
+    }
 }
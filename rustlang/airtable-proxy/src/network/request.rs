@@ -48,12 +48,12 @@ impl<T: Table> Param<T> {
 pub async fn one<U: Table>(ctx: &mut FetchCtx, param: Param<U>) -> Result<One<U::Fields>> {
     match param {
         Param::Query { key, value, .. } => {
-            let result: Many<U::Fields> = ctx.fetch_query(U::NAME, &key, &value).await?;
+            let result: Many<U::Fields> = ctx.fetch_query(U::SOURCE, &key, &value).await?;
             crate::transform::first(ctx, result.records).await
         }
         Param::IDs { ids, .. } => {
             if let Some(id) = ids.first() {
-                ctx.fetch_id(U::NAME, &id).await
+                ctx.fetch_id(U::SOURCE, &id).await
             } else {
                 Err(Error::RequestParams {
                     table: U::NAME,
@@ -68,7 +68,7 @@ pub async fn one<U: Table>(ctx: &mut FetchCtx, param: Param<U>) -> Result<One<U:
 pub async fn many<U: Table>(ctx: &mut FetchCtx, param: Param<U>) -> Result<List<U::Fields>> {
     Ok(match param {
         Param::Query { key, value, .. } => {
-            let result: Many<U::Fields> = ctx.fetch_query(U::NAME, &key, &value).await?;
+            let result: Many<U::Fields> = ctx.fetch_query(U::SOURCE, &key, &value).await?;
             result.records
         }
         Param::IDs { ids, .. } => {
@@ -77,7 +77,7 @@ pub async fn many<U: Table>(ctx: &mut FetchCtx, param: Param<U>) -> Result<List<
             // then we can accumulate them into the output vector.
             let mut output = Vec::with_capacity(ids.len());
             for id in ids {
-                output.push(ctx.fetch_id(U::NAME, &id).await?);
+                output.push(ctx.fetch_id(U::SOURCE, &id).await?);
             }
             output
         }
@@ -0,0 +1,342 @@
+//! The OpenAPI 3 spec of the proxy, served at `GET /openapi.json`, and when
+//! the config's `server.docs_ui` is on, a page that renders it at `GET /docs`.
+//!
+//! Each generated endpoint describes itself as an `Operation`, along with
+//! `list`, `batch`, and `raw`, and the routes every schema has, like the
+//! cache's. The `Mapped` types are the components, by their names.
+
+use crate::builder::{boxed, Mounted, Route};
+use crate::gen_schema::Table;
+use crate::shape::Shape;
+use serde_json::{json, Map, Value};
+use warp::Filter;
+
+/// A segment of the path of an `Operation`.
+#[derive(Debug, Clone)]
+pub enum Segment {
+    Literal(&'static str),
+    /// A parameter, the endpoints' are all named `arg`, and numbered when
+    /// there are several.
+    Param(&'static str, Shape),
+}
+
+/// What a route takes and replies with, and for a generated endpoint, what
+/// it runs, for the spec and `GET /_routes`.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    /// The name of the endpoint, like `query_by_invoice_id`.
+    pub name: &'static str,
+    /// The module of a generated endpoint.
+    pub module: Option<&'static str>,
+    pub method: &'static str,
+    pub path: Vec<Segment>,
+    /// The query string's parameters, which can be left out.
+    pub query: Vec<(&'static str, Shape)>,
+    pub body: Option<Shape>,
+    pub response: Shape,
+    /// The content type of the response, when it isn't JSON.
+    pub content_type: Option<&'static str>,
+    /// Whether it takes `?expand=`, `?fields=`, and `?keys=`.
+    pub scoped: bool,
+    /// The table of a generated endpoint.
+    pub table: Option<&'static str>,
+    /// The type of the argument of its `exec` chain, like `String`.
+    pub input: Option<&'static str>,
+    /// Its `exec` chain, as it's written.
+    pub exec: Option<&'static str>,
+}
+
+impl Operation {
+    /// A generated endpoint of the table `U`, `GET`, with no query or body,
+    /// taking `?expand=`, `?fields=`, and `?keys=`.
+    pub fn endpoint<U: Table>(name: &'static str, path: Vec<Segment>, response: Shape) -> Self {
+        Self {
+            name,
+            module: Some(U::MODULE_NAME),
+            method: "GET",
+            path,
+            query: vec![],
+            body: None,
+            response,
+            content_type: None,
+            scoped: true,
+            table: Some(U::NAME),
+            input: None,
+            exec: None,
+        }
+    }
+
+    /// A `GET` route of the proxy, that isn't a table's.
+    fn proxy(name: &'static str, path: Vec<Segment>, response: Shape) -> Self {
+        Self {
+            name,
+            module: None,
+            method: "GET",
+            path,
+            query: vec![],
+            body: None,
+            response,
+            content_type: None,
+            scoped: false,
+            table: None,
+            input: None,
+            exec: None,
+        }
+    }
+
+    /// The path, with `{name}` for each of its parameters, and those.
+    pub fn template(&self) -> (String, Vec<(String, &Shape)>) {
+        let count = |name: &str| {
+            self.path
+                .iter()
+                .filter(|segment| matches!(segment, Segment::Param(param, _) if *param == name))
+                .count()
+        };
+        let mut path = String::new();
+        let mut params: Vec<(String, &Shape)> = vec![];
+        for segment in &self.path {
+            let segment = match segment {
+                Segment::Literal(literal) => (*literal).to_owned(),
+                Segment::Param(name, shape) => {
+                    let name = match count(name) {
+                        1 => (*name).to_owned(),
+                        _ => format!("{}{}", name, params.len() + 1),
+                    };
+                    params.push((name.clone(), shape));
+                    format!("{{{}}}", name)
+                }
+            };
+            path.push('/');
+            path.push_str(&segment);
+        }
+        (path, params)
+    }
+}
+
+/// The query parameters of an endpoint's `query = T;`, the fields of `T`, or
+/// all of it as `query`, when it isn't an object.
+pub fn query_params(shape: Shape) -> Vec<(&'static str, Shape)> {
+    match shape {
+        Shape::Object(fields) => fields,
+        shape => vec![("query", shape)],
+    }
+}
+
+/// The routes every schema has, besides those of its tables.
+pub fn schema_operations() -> Vec<Operation> {
+    let stats = || Shape::Object(vec![("hits", Shape::Integer), ("misses", Shape::Integer)]);
+    vec![
+        Operation::proxy(
+            "cache_stats",
+            vec![Segment::Literal("cache"), Segment::Literal("stats")],
+            stats(),
+        ),
+        Operation::proxy(
+            "cache_clear",
+            vec![Segment::Literal("cache"), Segment::Literal("clear")],
+            stats(),
+        ),
+        Operation::proxy(
+            "cache_dump",
+            vec![Segment::Literal("cache"), Segment::Literal("dump")],
+            Shape::Any,
+        ),
+        Operation {
+            method: "POST",
+            body: Some(Shape::Any),
+            ..Operation::proxy(
+                "cache_load",
+                vec![Segment::Literal("cache"), Segment::Literal("load")],
+                Shape::Object(vec![("restored", Shape::Integer)]),
+            )
+        },
+        Operation {
+            content_type: Some("application/octet-stream"),
+            ..Operation::proxy(
+                "attachment",
+                vec![
+                    Segment::Literal("attachments"),
+                    Segment::Param("module", Shape::String(None)),
+                    Segment::Param("record_id", Shape::String(None)),
+                    Segment::Param("field", Shape::String(None)),
+                    Segment::Param("n", Shape::Integer),
+                ],
+                Shape::String(Some("binary")),
+            )
+        },
+        Operation {
+            method: "POST",
+            body: Some(Shape::Any),
+            ..Operation::proxy(
+                "webhook",
+                vec![Segment::Literal("webhooks"), Segment::Literal("airtable")],
+                Shape::Any,
+            )
+        },
+    ]
+}
+
+/// The routes of the proxy, that aren't any schema's.
+pub(crate) fn proxy_operations(docs_ui: bool) -> Vec<Operation> {
+    let mut operations = vec![
+        Operation::proxy("routes", vec![Segment::Literal("_routes")], Shape::Any),
+        Operation::proxy(
+            "openapi",
+            vec![Segment::Literal("openapi.json")],
+            Shape::Any,
+        ),
+    ];
+    if docs_ui {
+        operations.push(Operation {
+            content_type: Some("text/html"),
+            ..Operation::proxy("docs", vec![Segment::Literal("docs")], Shape::String(None))
+        });
+    }
+    operations
+}
+
+/// The object of one operation in the spec, its components are under `refs`.
+fn operation(
+    operation: &Operation,
+    id: String,
+    params: Vec<(String, &Shape)>,
+    refs: &str,
+) -> Value {
+    let mut parameters: Vec<Value> = params
+        .into_iter()
+        .map(|(name, shape)| {
+            json!({ "name": name, "in": "path", "required": true, "schema": shape.schema(refs) })
+        })
+        .collect();
+    let query = operation
+        .query
+        .iter()
+        .map(|(name, shape)| (*name, shape.non_null().clone()));
+    let scope = vec![
+        ("expand", Shape::String(None)),
+        ("fields", Shape::String(None)),
+        ("keys", Shape::String(None)),
+    ];
+    let scope = scope.into_iter().filter(|_| operation.scoped);
+    for (name, shape) in query.chain(scope) {
+        parameters.push(
+            json!({ "name": name, "in": "query", "required": false, "schema": shape.schema(refs) }),
+        );
+    }
+
+    let content_type = operation.content_type.unwrap_or("application/json");
+    let mut responses = json!({
+        "200": {
+            "description": "OK",
+            "content": { content_type: { "schema": operation.response.schema(refs) } },
+        },
+    });
+    if operation.module.is_some() {
+        let error = Shape::Object(vec![("error", Shape::String(None))]).schema(refs);
+        responses["400"] = json!({
+            "description": "A mistake in the request, like an unknown field",
+            "content": { "application/json": { "schema": error } },
+        });
+    }
+    let mut object = json!({
+        "operationId": id,
+        "tags": [operation.module.unwrap_or("proxy")],
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(body) = &operation.body {
+        object["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": body.schema(refs) } },
+        });
+    }
+    object
+}
+
+/// The spec of the schemas `mounted` in each namespace, and the proxy's own
+/// routes. The components of a namespace's are prefixed with its name.
+pub fn spec(mounted: &[Mounted], docs_ui: bool) -> Value {
+    let mut paths = Map::new();
+    let mut components = Map::new();
+    let global = (None, "", proxy_operations(docs_ui), vec![]);
+    let namespaces = mounted.iter().map(|mounted| {
+        (
+            mounted.namespace,
+            mounted.prefix.as_str(),
+            mounted.operations.clone(),
+            mounted.components.clone(),
+        )
+    });
+    for (namespace, prefix, operations, shapes) in std::iter::once(global).chain(namespaces) {
+        let scope = match namespace {
+            Some(namespace) => format!("{}.", namespace),
+            None => String::new(),
+        };
+        let refs = format!("#/components/schemas/{}", scope);
+        for (name, shape) in shapes {
+            components.insert(format!("{}{}", scope, name), shape.schema(&refs));
+        }
+        for op in &operations {
+            let (path, params) = op.template();
+            let path = match prefix {
+                "" => path,
+                prefix => format!("/{}{}", prefix, path),
+            };
+            let id = match op.module {
+                Some(module) => format!("{}{}.{}", scope, module, op.name),
+                None => format!("{}{}", scope, op.name),
+            };
+            let methods = paths
+                .entry(path)
+                .or_insert_with(|| json!({}))
+                .as_object_mut()
+                .expect("the paths are objects");
+            // like the router, the first endpoint with a path gets it
+            methods
+                .entry(op.method.to_lowercase())
+                .or_insert_with(|| operation(op, id, params, &refs));
+        }
+    }
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "airtable-proxy",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": { "schemas": components },
+    })
+}
+
+/// The page at `GET /docs`, which renders the spec with redoc.
+const DOCS: &str = r#"<!DOCTYPE html>
+<html>
+  <head>
+    <meta charset="utf-8" />
+    <title>airtable-proxy</title>
+  </head>
+  <body>
+    <redoc spec-url="openapi.json"></redoc>
+    <script src="https://cdn.redoc.ly/redoc/v2.1.5/bundles/redoc.standalone.js" crossorigin="anonymous"></script>
+  </body>
+</html>
+"#;
+
+/// `GET /openapi.json`, and `GET /docs` with the `docs_ui`.
+pub fn route(mounted: &[Mounted], docs_ui: bool) -> Route {
+    let spec = spec(mounted, docs_ui);
+    let openapi = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(move || warp::reply::json(&spec));
+    let docs = warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and_then(move || async move {
+            match docs_ui {
+                true => Ok(warp::reply::html(DOCS)),
+                false => Err(warp::reject::not_found()),
+            }
+        });
+    boxed(openapi.or(docs))
+}
@@ -0,0 +1,101 @@
+//! The records as airtable sends them, to tell whether a record that looks
+//! wrong is wrong in airtable, or in a transform.
+//!
+//! `GET /_raw/{module}/{record_id}` is only served when the config's
+//! `server.raw_endpoint` is on. It's the record's `id`, `createdTime`, and
+//! `fields`, read through the cache, like the record would be for the other
+//! endpoints. With `?compare=true`, it's each field's column, its `raw`
+//! value, and its `mapped` value, after its `exec`, side by side.
+
+use crate::airtable::FetchCtx;
+use crate::error::Error;
+use crate::gen_schema::Table;
+use crate::network::response::One;
+use crate::openapi::{Operation, Segment};
+use crate::shape::Shape;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A record, with the fields airtable sent for it.
+pub type Raw = One<Map<String, Value>>;
+
+/// The query parameters of `GET /_raw/{module}/{record_id}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Query {
+    #[serde(default)]
+    pub compare: bool,
+}
+
+/// Fetches the record `id` of `U`, when airtable doesn't have it, it's `NotFound`.
+pub async fn fetch<U: Table, T: DeserializeOwned>(ctx: &mut FetchCtx, id: &str) -> Result<T> {
+    match ctx.fetch_id(U::SOURCE, id).await {
+        Err(Error::Response { status, .. }) if status.starts_with("404") => Err(Error::NotFound {
+            table: U::NAME,
+            key: id.to_owned(),
+        }),
+        result => result,
+    }
+}
+
+/// The `Fields` of the `raw` record, to create the `Mapped` from.
+pub fn fields<U: Table>(raw: &Raw) -> Result<One<U::Fields>> {
+    Ok(One {
+        id: raw.id.clone(),
+        fields: serde_json::from_value(Value::Object(raw.fields.clone()))
+            .map_err(Error::SerdeTransform)?,
+        created_time: raw.created_time.clone(),
+    })
+}
+
+/// The `raw` record, with the `mapped` value next to the raw one for each of
+/// the fields, the columns that none of them are read from, and the error, if
+/// the record couldn't be mapped.
+pub fn compare<U: Table>(mut raw: Raw, mapped: Result<Value>) -> Value {
+    let (mut mapped, error) = match mapped {
+        Ok(Value::Object(mapped)) => (mapped, None),
+        Ok(_) => (Map::new(), None),
+        Err(e) => (Map::new(), Some(e.chain())),
+    };
+    let mut fields = Map::new();
+    for name in U::FIELD_NAMES {
+        let column = U::COLUMNS
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, column)| *column);
+        let value = column.and_then(|column| raw.fields.remove(column));
+        fields.insert(
+            (*name).to_owned(),
+            json!({
+                "column": column,
+                "raw": value,
+                "mapped": mapped.remove(*name),
+            }),
+        );
+    }
+    json!({
+        "id": raw.id,
+        "createdTime": raw.created_time,
+        "fields": fields,
+        "unmapped": raw.fields,
+        "error": error,
+    })
+}
+
+/// The raw records of `U`, for the spec.
+pub fn operation<U: Table>() -> Operation {
+    let path = vec![
+        Segment::Literal("_raw"),
+        Segment::Literal(U::MODULE_NAME),
+        Segment::Param("record_id", Shape::String(None)),
+    ];
+    Operation {
+        query: vec![("compare", Shape::Boolean)],
+        scoped: false,
+        input: Some("String"),
+        exec: Some("raw::fetch"),
+        ..Operation::endpoint::<U>("raw", path, Shape::Any)
+    }
+}
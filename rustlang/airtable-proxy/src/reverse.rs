@@ -0,0 +1,111 @@
+//! The records of another table that link to a record, like a client's
+//! invoices, for the fields in a table's `reverse` block.
+//!
+//! A reverse field is only fetched when it's expanded, `?expand=invoices`,
+//! otherwise it's `null`. The records are found by the other table's link
+//! column, `source = "Client";`, going through the records with anything in
+//! it, since airtable's formulas see the names of linked records, and not
+//! their IDs, or with `inverse = "Invoices";`, by the IDs in the link column
+//! airtable adds to this table for it, which is one request per record.
+//!
+//! Going through the other table's records happens once a request, they're
+//! grouped by the IDs they link to for the rest of it, and it stops after
+//! `MAX_PAGES` of them, so a big table can leave some of the records out.
+
+use crate::airtable::FetchCtx;
+use crate::error::Error;
+use crate::gen_schema::Table;
+use crate::list::{ListParams, Page};
+use crate::network::request::{many, Param};
+use crate::network::response::List;
+use crate::raw::Raw;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The most pages of the other table's records read for a request.
+const MAX_PAGES: usize = 10;
+
+/// The records linking to each record ID, by the base, table, and column of
+/// their links, for the request being handled.
+pub(crate) type Linking =
+    HashMap<(&'static str, &'static str, &'static str), HashMap<String, Vec<Raw>>>;
+
+/// Where the IDs of the records linking to a record are.
+#[derive(Debug, Clone, Copy)]
+pub enum Reverse {
+    /// In this column of their table.
+    Link(&'static str),
+    /// In this column of the record's table, that's the inverse of theirs.
+    Inverse(&'static str),
+}
+
+/// Fetches the records of `T` that link to the record `id` of `U`.
+pub async fn fetch<U: Table, T: Table>(
+    ctx: &mut FetchCtx,
+    id: &str,
+    by: Reverse,
+) -> Result<List<T::Fields>> {
+    match by {
+        Reverse::Inverse(column) => {
+            let raw: Raw = crate::raw::fetch::<U, _>(ctx, id).await?;
+            many(ctx, Param::<T>::new_id(linked_ids(&raw.fields, column))).await
+        }
+        Reverse::Link(column) => {
+            let key = (T::SOURCE.base, T::SOURCE.table, column);
+            if !ctx.linking.contains_key(&key) {
+                let linking = linking::<T>(ctx, column).await?;
+                ctx.linking.insert(key, linking);
+            }
+            ctx.linking[&key]
+                .get(id)
+                .cloned()
+                .unwrap_or_default()
+                .into_iter()
+                .map(crate::list::fields)
+                .collect()
+        }
+    }
+}
+
+/// The IDs of the records linked to in the `column` of the `fields`.
+fn linked_ids(fields: &Map<String, Value>, column: &str) -> Vec<String> {
+    match fields.get(column) {
+        Some(Value::Array(ids)) => ids
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The records of `T` with anything in their link `column`, grouped by the
+/// IDs they link to, from the first `MAX_PAGES` of them.
+async fn linking<T: Table>(ctx: &mut FetchCtx, column: &str) -> Result<HashMap<String, Vec<Raw>>> {
+    let mut linking: HashMap<String, Vec<Raw>> = HashMap::new();
+    let mut params = ListParams {
+        formula: Some(format!("{{{}}}", column)),
+        ..Default::default()
+    };
+    for _ in 0..MAX_PAGES {
+        let page: Page = ctx.fetch_list(T::SOURCE, &params).await?;
+        for record in page.records {
+            for id in linked_ids(&record.fields, column) {
+                linking.entry(id).or_default().push(record.clone());
+            }
+        }
+        params.offset = match page.offset {
+            Some(offset) => Some(offset),
+            None => return Ok(linking),
+        };
+    }
+    crate::warn!(
+        "reverse | only read the first {} pages of {} linked by {}",
+        MAX_PAGES,
+        T::NAME,
+        column
+    );
+    Ok(linking)
+}
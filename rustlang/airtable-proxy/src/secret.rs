@@ -0,0 +1,62 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// A value, like an API key, that shouldn't end up in logs.
+///
+/// Both `Debug` and `Display` redact it, the only way to read the value
+/// is `expose`, which we do right before sending it to airtable.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Self(value)
+    }
+
+    /// Reads a secret from a file, like a docker or kubernetes secret,
+    /// without the trailing newline those usually have.
+    pub fn from_file(path: &str) -> std::io::Result<Self> {
+        let value = std::fs::read_to_string(path)?;
+        Ok(Self(value.trim_end().to_owned()))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "Secret([redacted])")
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "[redacted]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::new("keyABC".to_owned());
+        assert_eq!(format!("{}", secret), "[redacted]");
+        assert_eq!(format!("{:?}", secret), "Secret([redacted])");
+        assert_eq!(secret.expose(), "keyABC");
+    }
+
+    #[test]
+    fn from_file_trims_the_end() {
+        let path = std::env::temp_dir().join(format!("secret-{}", std::process::id()));
+        std::fs::write(&path, " keyABC\n\n").unwrap();
+        let secret = Secret::from_file(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(secret.unwrap().expose(), " keyABC");
+        assert!(Secret::from_file(path.to_str().unwrap()).is_err());
+    }
+}
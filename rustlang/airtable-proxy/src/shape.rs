@@ -0,0 +1,280 @@
+//! What the JSON of the types in a schema looks like, for the OpenAPI spec,
+//! and the TypeScript and JSON Schema that `types` generates.
+//!
+//! Every type a `Mapped` has a field of, and the types in the paths, queries,
+//! bodies, and returns of the endpoints, has to be `Describe`. The `Mapped`
+//! types are themselves, by name, as a reference to their component.
+
+use crate::expand::Link;
+use crate::field_types::{Collaborator, Date, DateTime, Decimal};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// A string, with the `format` of its contents, like `date`.
+    String(Option<&'static str>),
+    Integer,
+    Number,
+    Boolean,
+    Array(Box<Shape>),
+    /// An object with each of these fields, which are always there.
+    Object(Vec<(&'static str, Shape)>),
+    /// An object with these fields, which can be left out, and maybe others,
+    /// like a record of a `Mapped` type, which `?fields=` can leave out, and
+    /// `?keys=` can rename.
+    Record(Vec<(&'static str, Shape)>),
+    /// An object with any keys, and values like this.
+    Map(Box<Shape>),
+    Nullable(Box<Shape>),
+    /// Any of these, like a `Link`, which is IDs or the linked records.
+    AnyOf(Vec<Shape>),
+    /// The `Mapped` type with this name, the component it is.
+    Ref(&'static str),
+    /// Any JSON at all.
+    Any,
+}
+
+impl Shape {
+    /// The JSON Schema (as OpenAPI 3.1 has it) of this shape, `refs` is the
+    /// prefix of the references to the `Mapped` types, like `#/components/schemas/`.
+    pub fn schema(&self, refs: &str) -> Value {
+        match self {
+            Shape::String(None) => json!({ "type": "string" }),
+            Shape::String(Some(format)) => json!({ "type": "string", "format": format }),
+            Shape::Integer => json!({ "type": "integer" }),
+            Shape::Number => json!({ "type": "number" }),
+            Shape::Boolean => json!({ "type": "boolean" }),
+            Shape::Array(items) => json!({ "type": "array", "items": items.schema(refs) }),
+            Shape::Object(fields) => {
+                let required: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+                json!({
+                    "type": "object",
+                    "properties": properties(fields, refs),
+                    "required": required,
+                    "additionalProperties": false,
+                })
+            }
+            Shape::Record(fields) => {
+                json!({ "type": "object", "properties": properties(fields, refs) })
+            }
+            Shape::Map(values) => {
+                json!({ "type": "object", "additionalProperties": values.schema(refs) })
+            }
+            Shape::Nullable(shape) => json!({ "anyOf": [shape.schema(refs), { "type": "null" }] }),
+            Shape::AnyOf(shapes) => {
+                let shapes: Vec<Value> = shapes.iter().map(|shape| shape.schema(refs)).collect();
+                json!({ "anyOf": shapes })
+            }
+            Shape::Ref(name) => json!({ "$ref": format!("{}{}", refs, name) }),
+            Shape::Any => json!({}),
+        }
+    }
+
+    /// The TypeScript type of this shape, the `Mapped` types are their
+    /// interfaces, by name. An object's fields are a level past `indent`.
+    pub fn typescript(&self, indent: usize) -> String {
+        match self {
+            Shape::String(_) => "string".to_owned(),
+            Shape::Integer | Shape::Number => "number".to_owned(),
+            Shape::Boolean => "boolean".to_owned(),
+            Shape::Array(items) => match **items {
+                Shape::Nullable(_) | Shape::AnyOf(_) => format!("({})[]", items.typescript(indent)),
+                _ => format!("{}[]", items.typescript(indent)),
+            },
+            Shape::Object(fields) | Shape::Record(fields) if fields.is_empty() => "{}".to_owned(),
+            Shape::Object(fields) | Shape::Record(fields) => {
+                let optional = if matches!(self, Shape::Record(_)) { "?" } else { "" };
+                let mut object = String::from("{\n");
+                for (name, shape) in fields {
+                    object.push_str(&"  ".repeat(indent + 1));
+                    object.push_str(&format!(
+                        "{}{}: {};\n",
+                        name,
+                        optional,
+                        shape.typescript(indent + 1)
+                    ));
+                }
+                object.push_str(&"  ".repeat(indent));
+                object.push('}');
+                object
+            }
+            Shape::Map(values) => format!("Record<string, {}>", values.typescript(indent)),
+            Shape::Nullable(shape) => format!("{} | null", shape.typescript(indent)),
+            Shape::AnyOf(shapes) => {
+                let shapes: Vec<String> = shapes
+                    .iter()
+                    .map(|shape| shape.typescript(indent))
+                    .collect();
+                shapes.join(" | ")
+            }
+            Shape::Ref(name) => (*name).to_owned(),
+            Shape::Any => "unknown".to_owned(),
+        }
+    }
+
+    /// The shape, when it isn't `null`, like for a query parameter that's left out instead.
+    pub fn non_null(&self) -> &Shape {
+        match self {
+            Shape::Nullable(shape) => shape.non_null(),
+            shape => shape,
+        }
+    }
+}
+
+/// The JSON Schema of each of the `fields` of an object, by name.
+fn properties(fields: &[(&'static str, Shape)], refs: &str) -> Map<String, Value> {
+    fields
+        .iter()
+        .map(|(name, shape)| ((*name).to_owned(), shape.schema(refs)))
+        .collect()
+}
+
+/// A type whose JSON has a `Shape`.
+pub trait Describe {
+    fn shape() -> Shape;
+}
+
+macro_rules! describe {
+    ($shape:expr => $($type:ty),*) => {
+        $(impl Describe for $type {
+            fn shape() -> Shape {
+                $shape
+            }
+        })*
+    };
+}
+
+describe!(Shape::String(None) => String, &'static str);
+describe!(Shape::Integer => u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+describe!(Shape::Number => f32, f64);
+describe!(Shape::Boolean => bool);
+describe!(Shape::String(Some("decimal")) => Decimal);
+describe!(Shape::String(Some("date")) => Date);
+describe!(Shape::String(Some("date-time")) => DateTime);
+describe!(Shape::Any => Value, ());
+
+/// Airtable's, so it's open to the fields it adds.
+impl Describe for Collaborator {
+    fn shape() -> Shape {
+        Shape::Record(vec![
+            ("id", Shape::String(None)),
+            ("email", Shape::String(None)),
+            ("name", Option::<String>::shape()),
+        ])
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn shape() -> Shape {
+        Shape::Nullable(Box::new(T::shape()))
+    }
+}
+
+impl<T: Describe> Describe for Vec<T> {
+    fn shape() -> Shape {
+        Shape::Array(Box::new(T::shape()))
+    }
+}
+
+impl<T: Describe> Describe for HashMap<String, T> {
+    fn shape() -> Shape {
+        Shape::Map(Box::new(T::shape()))
+    }
+}
+
+impl<T: Describe> Describe for BTreeMap<String, T> {
+    fn shape() -> Shape {
+        Shape::Map(Box::new(T::shape()))
+    }
+}
+
+/// The IDs of the linked records, or those records, when they're expanded.
+impl<T: Describe> Describe for Link<T> {
+    fn shape() -> Shape {
+        Shape::AnyOf(vec![Vec::<String>::shape(), T::shape()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REFS: &str = "#/components/schemas/";
+
+    fn record() -> Shape {
+        Shape::Record(vec![
+            ("number", String::shape()),
+            ("date", Option::<Date>::shape()),
+            ("client", Link::<Client>::shape()),
+            ("items", Vec::<Option<u32>>::shape()),
+        ])
+    }
+
+    /// A `Mapped` type, as its component.
+    struct Client;
+
+    impl Describe for Client {
+        fn shape() -> Shape {
+            Shape::Ref("InvoiceClient")
+        }
+    }
+
+    #[test]
+    fn typescript() {
+        assert_eq!(
+            record().typescript(0),
+            "{\n  number?: string;\n  date?: string | null;\n  \
+             client?: string[] | InvoiceClient;\n  items?: (number | null)[];\n}"
+        );
+        assert_eq!(
+            Shape::Object(vec![("id", Shape::String(None))]).typescript(1),
+            "{\n    id: string;\n  }"
+        );
+        assert_eq!(
+            HashMap::<String, Value>::shape().typescript(0),
+            "Record<string, unknown>"
+        );
+        assert_eq!(Shape::Record(vec![]).typescript(0), "{}");
+        assert_eq!(
+            Collaborator::shape().typescript(0),
+            "{\n  id?: string;\n  email?: string;\n  name?: string | null;\n}"
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        assert_eq!(
+            record().schema(REFS),
+            json!({
+                "type": "object",
+                "properties": {
+                    "number": { "type": "string" },
+                    "date": { "anyOf": [{ "type": "string", "format": "date" }, { "type": "null" }] },
+                    "client": { "anyOf": [
+                        { "type": "array", "items": { "type": "string" } },
+                        { "$ref": "#/components/schemas/InvoiceClient" },
+                    ] },
+                    "items": { "type": "array", "items": {
+                        "anyOf": [{ "type": "integer" }, { "type": "null" }],
+                    } },
+                },
+            })
+        );
+        assert_eq!(
+            Shape::Object(vec![("id", Shape::String(None))]).schema(REFS),
+            json!({
+                "type": "object",
+                "properties": { "id": { "type": "string" } },
+                "required": ["id"],
+                "additionalProperties": false,
+            })
+        );
+    }
+
+    #[test]
+    fn non_null() {
+        assert_eq!(Option::<bool>::shape().non_null(), &Shape::Boolean);
+        assert_eq!(Shape::Integer.non_null(), &Shape::Integer);
+    }
+}
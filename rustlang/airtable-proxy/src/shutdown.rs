@@ -0,0 +1,43 @@
+//! Stopping the server without dropping the requests it's in the middle of.
+
+use crate::ctx::Ctx;
+use std::time::Duration;
+
+/// Resolves when the process is asked to stop, with SIGTERM or SIGINT (ctrl-c).
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => crate::info!("Got SIGTERM."),
+                    _ = tokio::signal::ctrl_c() => crate::info!("Got SIGINT."),
+                }
+                return;
+            }
+            Err(e) => crate::warn!("Could not listen for SIGTERM, only SIGINT will stop. {}", e),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        crate::warn!("Could not listen for SIGINT. {}", e);
+    }
+}
+
+/// Saves anything that needs to outlive the process, like the cache snapshot.
+///
+/// A request that didn't finish in time may still be holding the context, so
+/// this only waits `timeout` for it, and skips the flush after that.
+pub async fn flush(ctx: Ctx, timeout: Duration) {
+    match tokio::time::timeout(timeout, ctx.lock()).await {
+        Ok(ctx) => {
+            if let Err(e) = ctx.flush() {
+                crate::warn!("Could not flush the cache. {}", e);
+            }
+        }
+        Err(_) => crate::warn!(
+            "Could not flush the cache, it was still in use after {:?}.",
+            timeout
+        ),
+    }
+}
@@ -1,9 +1,41 @@
 #![allow(unused)]
 
 use crate::error::Error;
+use serde::{Deserialize, Serialize};
 
 pub type MaybeBool = Option<bool>;
 pub type IDs = Vec<String>;
+pub type Attachments = Vec<Attachment>;
+
+/// The shape of one file in an airtable attachment field.
+///
+/// The `url`s that airtable gives back expire after a while, so
+/// to link to the file itself, use the `/attachments` endpoint.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Attachment {
+    pub id: String,
+    pub url: String,
+    pub filename: String,
+    pub size: u64,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    pub thumbnails: Option<Thumbnails>,
+}
+
+/// Thumbnails airtable generates for image (and some document) attachments.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Thumbnails {
+    pub small: Thumbnail,
+    pub large: Thumbnail,
+    pub full: Option<Thumbnail>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
 
 #[macro_export(local_inner_macros)]
 macro_rules! compose {
@@ -0,0 +1,365 @@
+//! Receives airtable's webhook notifications and drops whatever they've made
+//! stale from the cache of the `FetchCtx`.
+//!
+//! A notification only tells us _that_ something changed, so we then fetch the
+//! webhook's payloads (starting from the last cursor we've seen) to find out
+//! which records those were. The cursors are saved in the cache snapshot, so
+//! a restart doesn't read all of a webhook's payloads again.
+//!
+//! This needs `AIRTABLE_WEBHOOK_SECRET`, the `macSecretBase64` airtable gives
+//! back when creating the webhook, without it the route answers 503. A missing
+//! or wrong signature is a 401.
+
+use crate::airtable::FetchCtx;
+use crate::ctx::{with_ctx, Ctx};
+use crate::error::Error;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::reply::{json, with_status, Json, WithStatus};
+use warp::{Filter, Rejection, Reply};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const SIGNATURE_HEADER: &str = "x-airtable-content-mac";
+const SIGNATURE_PREFIX: &str = "hmac-sha256=";
+
+/// Airtable's notifications are tiny, they only say which webhook fired,
+/// anything bigger isn't read before it's rejected.
+const MAX_BODY_BYTES: u64 = 4 * 1024;
+
+#[derive(Deserialize, Debug)]
+struct Id {
+    id: String,
+}
+
+/// The body of the notification airtable sends to our endpoint.
+#[derive(Deserialize, Debug)]
+struct Notification {
+    base: Id,
+    webhook: Id,
+}
+
+/// A page of payloads from `GET /bases/{base}/webhooks/{webhook}/payloads`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Payloads {
+    payloads: Vec<Payload>,
+    cursor: u64,
+    might_have_more: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    #[serde(default)]
+    changed_tables_by_id: HashMap<String, TableChanges>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TableChanges {
+    #[serde(default)]
+    changed_records_by_id: HashMap<String, Value>,
+    #[serde(default)]
+    destroyed_record_ids: Vec<String>,
+}
+
+fn new_mac(secret: &str) -> Result<Hmac<Sha256>> {
+    let secret = base64::decode(secret).map_err(|_| Error::Webhook {
+        message: "the configured secret is not base64",
+    })?;
+    Hmac::new_from_slice(&secret).map_err(|_| Error::Webhook {
+        message: "the configured secret is not a valid key",
+    })
+}
+
+/// Checks the `signature` header airtable sent is the HMAC of the `body`.
+fn verify(mut mac: Hmac<Sha256>, body: &[u8], signature: Option<&str>) -> Result<()> {
+    let signature = signature
+        .and_then(|signature| signature.strip_prefix(SIGNATURE_PREFIX))
+        .and_then(|hex| hex::decode(hex).ok())
+        .ok_or(Error::Webhook {
+            message: "missing or malformed signature",
+        })?;
+    mac.update(body);
+    mac.verify_slice(&signature).map_err(|_| Error::Webhook {
+        message: "signature mismatch",
+    })
+}
+
+/// Reads all the new payloads for the notification's webhook, and invalidates
+/// the cached records and queries they touch.
+async fn invalidate(ctx: &mut FetchCtx, notification: Notification) -> Result<usize> {
+    let base = notification.base.id;
+    if ctx.config().base_name(&base).is_none() {
+        return Err(Error::Webhook {
+            message: "notification is for a base that isn't configured",
+        });
+    }
+
+    let webhook = notification.webhook.id;
+    let mut cursor = ctx.webhook_cursors.get(&webhook).copied().unwrap_or(1);
+    let mut ids = HashSet::new();
+    let mut changed = false;
+    loop {
+        let page: Payloads = ctx.fetch_webhook_payloads(&base, &webhook, cursor).await?;
+        for payload in page.payloads {
+            for table in payload.changed_tables_by_id.values() {
+                changed = true;
+                ids.extend(table.changed_records_by_id.keys().cloned());
+                ids.extend(table.destroyed_record_ids.iter().cloned());
+            }
+        }
+        cursor = page.cursor;
+        if !page.might_have_more {
+            break;
+        }
+    }
+
+    crate::debug!(
+        "webhook | id={} cursor={} records={:?}",
+        webhook,
+        cursor,
+        ids
+    );
+    ctx.webhook_cursors.insert(webhook, cursor);
+    if !changed {
+        return Ok(0);
+    }
+    Ok(ctx.cache.invalidate(&base, &ids))
+}
+
+fn error(status: StatusCode, e: impl std::fmt::Display) -> WithStatus<Json> {
+    with_status(json(&json!({ "error": e.to_string() })), status)
+}
+
+async fn receive(
+    signature: Option<String>,
+    body: Bytes,
+    ctx: Ctx,
+) -> std::result::Result<WithStatus<Json>, Rejection> {
+    let mut ctx = ctx.lock().await;
+    let mac = match ctx.webhook_secret() {
+        Some(secret) => new_mac(secret).map_err(warp::reject::custom)?,
+        None => {
+            return Ok(error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "no webhook secret is configured",
+            ))
+        }
+    };
+    if let Err(e) = verify(mac, &body, signature.as_deref()) {
+        return Ok(error(StatusCode::UNAUTHORIZED, e));
+    }
+    let notification = match serde_json::from_slice(&body) {
+        Ok(notification) => notification,
+        Err(e) => return Ok(error(StatusCode::BAD_REQUEST, Error::SerdeTransform(e))),
+    };
+    match invalidate(&mut ctx, notification).await {
+        Ok(invalidated) => Ok(with_status(
+            json(&json!({ "invalidated": invalidated })),
+            StatusCode::OK,
+        )),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+pub fn route(ctx: Ctx) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    // POST /webhooks/airtable
+    warp::path("webhooks")
+        .and(warp::path("airtable"))
+        .and(warp::post())
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .and(warp::body::content_length_limit(MAX_BODY_BYTES))
+        .and(warp::body::bytes())
+        .and(with_ctx(ctx))
+        .and_then(receive)
+}
+
+/// Sends airtable-like notifications to the `route`, so we can test
+/// without airtable.
+pub mod mock {
+
+    use super::*;
+
+    /// The value of the signature header airtable would send for the `body`.
+    pub fn signature(secret: &str, body: &[u8]) -> Result<String> {
+        let mut mac = new_mac(secret)?;
+        mac.update(body);
+        Ok(format!(
+            "{}{}",
+            SIGNATURE_PREFIX,
+            hex::encode(mac.finalize().into_bytes())
+        ))
+    }
+
+    /// The body of a notification that `webhook` in `base` has new payloads.
+    pub fn body(base: &str, webhook: &str) -> String {
+        json!({
+            "base": { "id": base },
+            "webhook": { "id": webhook },
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })
+        .to_string()
+    }
+
+    /// A signed notification that `webhook` in `base` has new payloads.
+    pub fn notification(
+        secret: &str,
+        base: &str,
+        webhook: &str,
+    ) -> Result<warp::test::RequestBuilder> {
+        let body = body(base, webhook);
+        Ok(warp::test::request()
+            .method("POST")
+            .path("/webhooks/airtable")
+            .header(SIGNATURE_HEADER, signature(secret, body.as_bytes())?)
+            .body(body))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, DEFAULT};
+    use crate::ctx::wrap_ctx;
+    use crate::network::cache::{Snapshot, SnapshotEntry};
+    use crate::secret::Secret;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    const SECRET: &str = "c2VjcmV0";
+    const BASE: &str = "appBASE0000000001";
+    const WEBHOOK: &str = "achWEBHOOK0000001";
+
+    fn config(secret: Option<&str>) -> Config {
+        let mut config = Config::default();
+        config
+            .airtable
+            .bases
+            .insert(DEFAULT.to_owned(), BASE.to_owned());
+        config
+            .airtable
+            .keys
+            .insert(DEFAULT.to_owned(), Secret::new("key".to_owned()));
+        config.airtable.webhook_secret = secret.map(|secret| Secret::new(secret.to_owned()));
+        config
+    }
+
+    fn ctx(config: Config) -> Ctx {
+        wrap_ctx(FetchCtx::new(config).unwrap())
+    }
+
+    #[tokio::test]
+    async fn without_a_secret_its_unavailable() {
+        let route = route(ctx(config(None)));
+        let response = mock::notification(SECRET, BASE, WEBHOOK)
+            .unwrap()
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn a_bad_or_missing_signature_is_unauthorized() {
+        let route = route(ctx(config(Some(SECRET))));
+        let other = base64::encode("other");
+        let response = mock::notification(&other, BASE, WEBHOOK)
+            .unwrap()
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/webhooks/airtable")
+            .body(mock::body(BASE, WEBHOOK))
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn a_big_body_is_rejected() {
+        let route = route(ctx(config(Some(SECRET))));
+        let body = vec![b' '; MAX_BODY_BYTES as usize + 1];
+        let response = warp::test::request()
+            .method("POST")
+            .path("/webhooks/airtable")
+            .header(SIGNATURE_HEADER, mock::signature(SECRET, &body).unwrap())
+            .body(body)
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    /// Serves one page of payloads, where the record `changed` changed.
+    fn airtable(changed: &'static str) -> String {
+        let payloads = warp::path!("v0" / "bases" / String / "webhooks" / String / "payloads").map(
+            move |_, _| {
+                warp::reply::json(&json!({
+                    "cursor": 7,
+                    "mightHaveMore": false,
+                    "payloads": [{
+                        "changedTablesById": {
+                            "tblINVOICE000001": { "changedRecordsById": { changed: {} } },
+                        },
+                    }],
+                }))
+            },
+        );
+        let (address, server) = warp::serve(payloads).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}/v0/", address)
+    }
+
+    #[tokio::test]
+    async fn a_notification_invalidates_the_changed_records_and_the_bases_lists() {
+        let mut config = config(Some(SECRET));
+        config.airtable.api_url = airtable("recCHANGED0000001");
+        let ctx = ctx(config);
+        let url = |path: &str| format!("http://airtable/v0/{}", path);
+        let cached = [
+            url(&format!("{}/Invoice/recCHANGED0000001", BASE)),
+            url(&format!("{}/Invoice/recSAME0000000001", BASE)),
+            url(&format!("{}/Invoice?pageSize=10", BASE)),
+            url(&format!("{}/Clients?filterByFormula=1", BASE)),
+            url("appOTHER00000001/Invoice?pageSize=10"),
+        ];
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        ctx.lock().await.cache.restore(Snapshot {
+            responses: cached
+                .iter()
+                .map(|url| SnapshotEntry {
+                    url: url.clone(),
+                    value: json!({}),
+                    fetched_at: now.as_secs(),
+                })
+                .collect(),
+            webhook_cursors: HashMap::new(),
+        });
+
+        let response = mock::notification(SECRET, BASE, WEBHOOK)
+            .unwrap()
+            .reply(&route(ctx.clone()))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), br#"{"invalidated":3}"#);
+
+        let ctx = ctx.lock().await;
+        let mut left: Vec<String> = ctx
+            .cache
+            .snapshot()
+            .responses
+            .into_iter()
+            .map(|entry| entry.url)
+            .collect();
+        left.sort();
+        assert_eq!(left, vec![cached[1].clone(), cached[4].clone()]);
+        assert_eq!(ctx.webhook_cursors.get(WEBHOOK), Some(&7));
+    }
+}
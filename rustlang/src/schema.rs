@@ -1,22 +1,5 @@
-use airtable_proxy::error::Error;
 use airtable_proxy::*;
 
-//
-// FIXME: this doesn't do anything with decimals :(
-pure!(
-    fn money(val: u32) -> String {
-        use num_format::{Locale, WriteFormatted};
-        let mut buf = String::from("$");
-        if buf.write_formatted(&val, &Locale::en).is_err() {
-            return Err(Error::Transform {
-                message: "could not format money"
-            });
-        }
-        buf.push_str(".00");
-        Ok(buf)
-    }
-);
-
 gen_airtable_schema! {
 
     invoice_rate_unit("Invoice Units") -> InvoiceRateUnit {
@@ -41,8 +24,9 @@ gen_airtable_schema! {
             rate -> u32 {
                 source = "Rate";
             }
-            unit(IDs) -> String {
+            unit(IDs) -> Link<String> {
                 source = "Unit";
+                link;
                 exec = InvoiceRateUnit::fetch_and_create_first, invoice_rate_unit::get_name;
             }
         }
@@ -55,19 +39,40 @@ gen_airtable_schema! {
             }
             description {
                 source = "Description";
+                required;
             }
             quantity -> u32 {
                 source = "Quantity";
+                default = 0;
             }
-            amount(u32) -> String {
+            amount(Currency) -> String {
                 source = "Amount";
-                exec = money;
+                exec = format_currency;
             }
-            rate(IDs) -> InvoiceRate {
+            rate(IDs) -> Link<InvoiceRate> {
                 source = "Invoice Rate";
+                link;
                 exec = InvoiceRate::fetch_and_create_first;
             }
         }
+        computed {
+            subtotal -> Option<String> {
+                from = mapped;
+                exec = subtotal;
+            }
+        }
+        module {
+            /// The quantity times the rate, when the rate is expanded.
+            async fn subtotal(ctx: &FetchCtx, item: &Partial) -> Result<Option<String>, Error> {
+                match item.rate.expanded() {
+                    Some(rate) => {
+                        let subtotal = Currency::from(item.quantity) * Currency::from(rate.rate);
+                        format_currency(ctx, subtotal).await.map(Some)
+                    }
+                    None => Ok(None),
+                }
+            }
+        }
     }
 
     invoice_client("Clients") -> InvoiceClient {
@@ -85,6 +90,11 @@ gen_airtable_schema! {
                 source = "Website";
             }
         }
+        reverse {
+            invoices -> Invoice {
+                source = "Client";
+            }
+        }
     }
 
     invoice_from("Me") -> InvoiceFrom {
@@ -109,38 +119,47 @@ gen_airtable_schema! {
             }
             number {
                 source = "Invoice Number";
+                filter;
+                unique;
             }
             notes -> Option<String> {
                 source = "Notes";
             }
             date {
                 source = "Date";
+                filter;
             }
             due_date {
                 source = "Due Date";
             }
             was_sent(MaybeBool) -> bool {
                 source = "Sent?";
+                filter;
                 exec = force_bool;
             }
             was_paid(MaybeBool) -> bool {
                 source = "Paid?";
+                filter;
                 exec = force_bool;
             }
-            total(u32) -> String {
+            total(Currency) -> String {
                 source = "Total Amount";
-                exec = money;
+                exec = format_currency;
             }
-            from(IDs) -> InvoiceFrom {
+            from(IDs) -> Link<InvoiceFrom> {
                 source = "From";
+                link;
                 exec = InvoiceFrom::fetch_and_create_first;
             }
-            client(IDs) -> InvoiceClient {
+            client(IDs) -> Link<InvoiceClient> {
                 source = "Client";
+                link;
+                filter;
                 exec = InvoiceClient::fetch_and_create_first;
             }
-            items(IDs) -> Vec<InvoiceItem> {
+            items(IDs) -> Link<Vec<InvoiceItem>> {
                 source = "Invoice Item";
+                link;
                 exec = InvoiceItem::fetch_and_create_many;
             }
         }
@@ -153,6 +172,197 @@ gen_airtable_schema! {
                 url_path { String }
                 exec = into_vec, Invoice::fetch_and_create_first;
             }
+            find_by_record_ids(Vec<String>) -> Vec<Invoice> {
+                method = POST;
+                url_path { "records" }
+                body = Vec<String>;
+                exec = Invoice::fetch_and_create_many;
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::gen;
+    use airtable_proxy::airtable::FetchCtx;
+    use airtable_proxy::config::{Config, DEFAULT};
+    use airtable_proxy::ctx::wrap_ctx;
+    use airtable_proxy::secret::Secret;
+    use airtable_proxy::serde_json::{json, Value};
+    use airtable_proxy::warp::{self, Filter};
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const CLIENT: &str = "recCLIENT0000000A";
+    const OTHER_CLIENT: &str = "recCLIENT0000000B";
+
+    /// How many pages of invoices `airtable` has.
+    const PAGES: usize = 12;
+
+    /// Serves the invoices 1 to 36, in `PAGES` of 3, every third one is the
+    /// `CLIENT`'s, counting the pages `fetched`, and the two clients.
+    fn airtable(fetched: Arc<AtomicUsize>) -> String {
+        let invoices = warp::path!("v0" / String / "Invoice")
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |_, query: HashMap<String, String>| {
+                fetched.fetch_add(1, Ordering::SeqCst);
+                let page: usize = query
+                    .get("offset")
+                    .and_then(|offset| offset.strip_prefix("itrPAGE"))
+                    .map_or(1, |page| page.parse().unwrap());
+                let records: Vec<Value> = (page * 3 - 2..=page * 3)
+                    .map(|n| {
+                        let client = if n % 3 == 0 { CLIENT } else { OTHER_CLIENT };
+                        json!({
+                            "id": format!("recINVOICE00000{:02}", n),
+                            "createdTime": "2020-01-01T00:00:00.000Z",
+                            "fields": {
+                                "ID": n,
+                                "Invoice Number": format!("2020-{:03}", n),
+                                "Date": "2020-01-01",
+                                "Due Date": "2020-01-31",
+                                "Total Amount": 100,
+                                "From": [],
+                                "Client": [client],
+                                "Invoice Item": [],
+                            },
+                        })
+                    })
+                    .collect();
+                let offset = match page {
+                    PAGES => Value::Null,
+                    page => json!(format!("itrPAGE{}", page + 1)),
+                };
+                warp::reply::json(&json!({ "records": records, "offset": offset }))
+            });
+        let clients = warp::path!("v0" / String / "Clients").map(|_| {
+            let records: Vec<Value> = [CLIENT, OTHER_CLIENT]
+                .iter()
+                .map(|id| {
+                    json!({
+                        "id": id,
+                        "createdTime": "2020-01-01T00:00:00.000Z",
+                        "fields": {
+                            "Company": id,
+                            "ContactEmail": "",
+                            "ContactName": "",
+                            "Website": "",
+                        },
+                    })
+                })
+                .collect();
+            warp::reply::json(&json!({ "records": records }))
+        });
+        let (address, server) =
+            warp::serve(invoices.or(clients)).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        format!("http://{}/v0/", address)
+    }
+
+    #[tokio::test]
+    async fn decoded_paths_are_bad_requests() {
+        let ctx = wrap_ctx(FetchCtx::new(Config::default()).unwrap());
+        let route = gen::route(ctx);
+        for path in &[
+            "/invoice/rec%2F..%2F..%2FappX%2FT%2FrecY",
+            "/invoice/%2E%2E",
+            "/invoice/2020-001%3Ffields%3Did",
+        ] {
+            let response = warp::test::request().path(path).reply(&route).await;
+            assert_eq!(response.status(), 400, "{}", path);
+        }
+    }
+
+    /// The routes, reading from `airtable`, without a cache, so each page
+    /// that's read is `fetched`.
+    fn route(
+        fetched: Arc<AtomicUsize>,
+    ) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+        let mut config = Config::default();
+        config.airtable.api_url = airtable(fetched);
+        config.cache.enabled = false;
+        let default = DEFAULT.to_owned();
+        config
+            .airtable
+            .bases
+            .insert(default.clone(), "appBASE0000000001".to_owned());
+        config
+            .airtable
+            .keys
+            .insert(default, Secret::new("key".to_owned()));
+        gen::route(wrap_ctx(FetchCtx::new(config).unwrap()))
+    }
+
+    /// The numbers of the invoices on each page of the list at `first`,
+    /// following the cursors.
+    async fn pages(first: &str) -> Vec<Vec<Value>> {
+        let route = route(Arc::default());
+        let mut path = first.to_owned();
+        let mut numbers = vec![];
+        loop {
+            let response = warp::test::request().path(&path).reply(&route).await;
+            assert_eq!(response.status(), 200);
+            let page: Value = airtable_proxy::serde_json::from_slice(response.body()).unwrap();
+            let records = page["records"].as_array().unwrap();
+            numbers.push(
+                records
+                    .iter()
+                    .map(|record| record["number"].clone())
+                    .collect::<Vec<_>>(),
+            );
+            match page["cursor"].as_str() {
+                Some(cursor) => path = format!("{}&cursor={}", first, cursor),
+                None => break,
+            }
+        }
+        numbers
+    }
+
+    /// The numbers of the `CLIENT`'s invoices, from `from` to `to`.
+    fn numbers(from: usize, to: usize) -> Vec<Value> {
+        (from..=to)
+            .map(|n| json!(format!("2020-{:03}", n * 3)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn link_filters_page_through_the_table() {
+        let first = format!("/invoice?client={}&page_size=5&fields=number", CLIENT);
+        assert_eq!(
+            pages(&first).await,
+            vec![numbers(1, 5), numbers(6, 10), numbers(11, 12)]
+        );
+    }
+
+    #[tokio::test]
+    async fn link_filters_read_at_most_ten_pages_at_once() {
+        let first = format!("/invoice?client={}&page_size=20&fields=number", CLIENT);
+        assert_eq!(pages(&first).await, vec![numbers(1, 10), numbers(11, 12)]);
+    }
+
+    #[tokio::test]
+    async fn reverse_fields_read_the_other_table_once() {
+        let fetched = Arc::new(AtomicUsize::new(0));
+        let route = route(fetched.clone());
+        let response = warp::test::request()
+            .path("/invoice_client?expand=invoices&fields=company,invoices.number")
+            .reply(&route)
+            .await;
+        assert_eq!(response.status(), 200);
+        let clients: Value = airtable_proxy::serde_json::from_slice(response.body()).unwrap();
+        let invoices = |n: usize| {
+            clients["records"][n]["invoices"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|invoice| invoice["number"].clone())
+                .collect::<Vec<_>>()
+        };
+        // only the first ten pages are read, once for both clients
+        assert_eq!(invoices(0), numbers(1, 10));
+        assert_eq!(invoices(1).len(), 20);
+        assert_eq!(fetched.load(Ordering::SeqCst), 10);
+    }
+}
@@ -0,0 +1,334 @@
+//! The invoice schema, again, with `#[derive(AirtableTable)]`, which has to
+//! make the same proxy as the DSL does in `src/schema.rs`.
+
+use airtable_proxy::builder::{Proxy, ProxyBuilder, Schema};
+use airtable_proxy::config::{Config, DEFAULT};
+use airtable_proxy::secret::Secret;
+use airtable_proxy::serde_json::{self, json, Value};
+use airtable_proxy::warp::{self, Filter, Reply};
+
+#[path = "../src/schema.rs"]
+mod schema;
+
+mod derived {
+    use airtable_proxy::airtable::FetchCtx;
+    use airtable_proxy::error::Error;
+    use airtable_proxy::field_types::*;
+    use airtable_proxy::*;
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Invoice Units")]
+    pub struct InvoiceRateUnit {
+        #[airtable(source = "Name")]
+        pub name: String,
+    }
+
+    pure!(get_name(unit: InvoiceRateUnit) -> String { unit.name });
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Invoice Rates", module = "invoice_item_rate")]
+    pub struct InvoiceRate {
+        #[airtable(source = "Name")]
+        pub name: String,
+        #[airtable(source = "Notes")]
+        pub notes: Option<String>,
+        #[airtable(source = "Rate")]
+        pub rate: u32,
+        #[airtable(
+            source = "Unit",
+            from = IDs,
+            link,
+            exec = [InvoiceRateUnit::fetch_and_create_first, get_name]
+        )]
+        pub unit: Link<String>,
+    }
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Invoice Item")]
+    pub struct InvoiceItem {
+        #[airtable(source = "Date")]
+        pub date: String,
+        #[airtable(source = "Description", required)]
+        pub description: String,
+        #[airtable(source = "Quantity", default = 0)]
+        pub quantity: u32,
+        #[airtable(source = "Amount", from = Currency, exec = format_currency)]
+        pub amount: String,
+        #[airtable(
+            source = "Invoice Rate",
+            from = IDs,
+            link,
+            exec = InvoiceRate::fetch_and_create_first
+        )]
+        pub rate: Link<InvoiceRate>,
+        #[airtable(computed = mapped, exec = subtotal)]
+        pub subtotal: Option<String>,
+    }
+
+    /// The quantity times the rate, when the rate is expanded.
+    async fn subtotal(
+        ctx: &FetchCtx,
+        item: &invoice_item::Partial,
+    ) -> Result<Option<String>, Error> {
+        match item.rate.expanded() {
+            Some(rate) => {
+                let subtotal = Currency::from(item.quantity) * Currency::from(rate.rate);
+                format_currency(ctx, subtotal).await.map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Clients")]
+    pub struct InvoiceClient {
+        #[airtable(source = "Company")]
+        pub company: String,
+        #[airtable(source = "ContactEmail")]
+        pub contact_email: String,
+        #[airtable(source = "ContactName")]
+        pub contact_name: String,
+        #[airtable(source = "Website")]
+        pub website_url: String,
+        #[airtable(reverse = Invoice, source = "Client")]
+        pub invoices: Option<Vec<Invoice>>,
+    }
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Me")]
+    pub struct InvoiceFrom {
+        #[airtable(source = "Name")]
+        pub name: String,
+        #[airtable(source = "Email")]
+        pub email: String,
+        #[airtable(source = "Address", from = String, exec = split_lines)]
+        pub address: Vec<String>,
+    }
+
+    #[derive(Debug, Serialize, AirtableTable)]
+    #[airtable(table = "Invoice")]
+    #[airtable(
+        endpoint = query_by_invoice_id,
+        arg = String,
+        exec = [param::as_id_query, one, Invoice::create_one]
+    )]
+    #[airtable(
+        endpoint = find_by_record_id,
+        arg = String,
+        exec = [into_vec, Invoice::fetch_and_create_first]
+    )]
+    #[airtable(
+        endpoint = find_by_record_ids,
+        arg = Vec<String>,
+        returns = Vec<Invoice>,
+        method = POST,
+        path = ["records"],
+        body = Vec<String>,
+        exec = Invoice::fetch_and_create_many
+    )]
+    pub struct Invoice {
+        #[airtable(source = "ID")]
+        pub id: u32,
+        #[airtable(source = "Invoice Number", filter, unique)]
+        pub number: String,
+        #[airtable(source = "Notes")]
+        pub notes: Option<String>,
+        #[airtable(source = "Date", filter)]
+        pub date: String,
+        #[airtable(source = "Due Date")]
+        pub due_date: String,
+        #[airtable(source = "Sent?", from = MaybeBool, filter, exec = force_bool)]
+        pub was_sent: bool,
+        #[airtable(source = "Paid?", from = MaybeBool, filter, exec = force_bool)]
+        pub was_paid: bool,
+        #[airtable(source = "Total Amount", from = Currency, exec = format_currency)]
+        pub total: String,
+        #[airtable(
+            source = "From",
+            from = IDs,
+            link,
+            exec = InvoiceFrom::fetch_and_create_first
+        )]
+        pub from: Link<InvoiceFrom>,
+        #[airtable(
+            source = "Client",
+            from = IDs,
+            link,
+            filter,
+            exec = InvoiceClient::fetch_and_create_first
+        )]
+        pub client: Link<InvoiceClient>,
+        #[airtable(
+            source = "Invoice Item",
+            from = IDs,
+            link,
+            exec = InvoiceItem::fetch_and_create_many
+        )]
+        pub items: Link<Vec<InvoiceItem>>,
+    }
+
+    gen_derived_schema!(
+        invoice_rate_unit,
+        invoice_item_rate,
+        invoice_item,
+        invoice_client,
+        invoice_from,
+        invoice,
+    );
+}
+
+/// The records of each table, by the name in its url.
+fn tables() -> Value {
+    json!({
+        "Invoice": {
+            "recINVOICE0000001": {
+                "ID": 1, "Invoice Number": "2020-001", "Date": "2020-01-01",
+                "Due Date": "2020-01-31", "Sent?": true, "Total Amount": 1500.5,
+                "From": ["recME00000000001"], "Client": ["recCLIENT00000001"],
+                "Invoice Item": ["recITEM000000001", "recITEM000000002"],
+            },
+            "recINVOICE0000002": {
+                "ID": 2, "Invoice Number": "2020-002", "Notes": "late",
+                "Date": "2020-02-01", "Due Date": "2020-02-28", "Paid?": true,
+                "Total Amount": 100, "From": ["recME00000000001"],
+                "Client": ["recCLIENT00000002"], "Invoice Item": ["recITEM000000002"],
+            },
+        },
+        "Clients": {
+            "recCLIENT00000001": {
+                "Company": "ACME", "ContactEmail": "wile@acme.com",
+                "ContactName": "Wile", "Website": "acme.com",
+            },
+            "recCLIENT00000002": {
+                "Company": "Other", "ContactEmail": "o@other.com",
+                "ContactName": "O", "Website": "other.com",
+            },
+        },
+        "Me": {
+            "recME00000000001": { "Name": "Stan", "Email": "s@x.com", "Address": "1 St\nNYC" },
+        },
+        "Invoice%20Item": {
+            "recITEM000000001": {
+                "Date": "2020-01-01", "Description": "Work", "Quantity": 10,
+                "Amount": 1000, "Invoice Rate": ["recRATE000000001"],
+            },
+            "recITEM000000002": {
+                "Date": "2020-01-02", "Description": "More", "Amount": 500.5,
+                "Invoice Rate": ["recRATE000000001"],
+            },
+        },
+        "Invoice%20Rates": {
+            "recRATE000000001": { "Name": "Hourly", "Rate": 100, "Unit": ["recUNIT000000001"] },
+        },
+        "Invoice%20Units": {
+            "recUNIT000000001": { "Name": "hour" },
+        },
+    })
+}
+
+fn record(id: &str, fields: &Value) -> Value {
+    json!({ "id": id, "createdTime": "2020-01-01T00:00:00.000Z", "fields": fields })
+}
+
+/// Serves the `tables`, a record by its ID, or all of a table's records,
+/// without looking at the formula, as one page.
+fn airtable() -> String {
+    let by_id = warp::path!("v0" / String / String / String).map(|_, table: String, id: String| {
+        match tables()[&table].get(&id) {
+            Some(fields) => warp::reply::json(&record(&id, fields)).into_response(),
+            None => warp::http::StatusCode::NOT_FOUND.into_response(),
+        }
+    });
+    let list = warp::path!("v0" / String / String).map(|_, table: String| {
+        let records: Vec<Value> = match &tables()[&table] {
+            Value::Object(records) => records
+                .iter()
+                .map(|(id, fields)| record(id, fields))
+                .collect(),
+            _ => vec![],
+        };
+        warp::reply::json(&json!({ "records": records })).into_response()
+    });
+    let (address, server) = warp::serve(by_id.or(list)).bind_ephemeral(([127, 0, 0, 1], 0));
+    tokio::spawn(server);
+    format!("http://{}/v0/", address)
+}
+
+fn proxy<S: Schema>(api_url: &str) -> Proxy {
+    let mut config = Config::default();
+    config.airtable.api_url = api_url.to_owned();
+    let default = DEFAULT.to_owned();
+    config
+        .airtable
+        .bases
+        .insert(default.clone(), "appBASE0000000001".to_owned());
+    config
+        .airtable
+        .keys
+        .insert(default, Secret::new("key".to_owned()));
+    ProxyBuilder::new(config).schema::<S>().build().unwrap()
+}
+
+#[tokio::test]
+async fn the_routes_and_spec_are_the_same() {
+    let dsl = proxy::<schema::gen::Schema>("http://127.0.0.1:1/v0/");
+    let derived = proxy::<derived::gen::Schema>("http://127.0.0.1:1/v0/");
+    assert_eq!(
+        serde_json::to_value(&derived.routes).unwrap(),
+        serde_json::to_value(&dsl.routes).unwrap()
+    );
+    assert_eq!(
+        airtable_proxy::openapi::spec(&derived.mounted, true),
+        airtable_proxy::openapi::spec(&dsl.mounted, true)
+    );
+}
+
+#[tokio::test]
+async fn the_responses_are_the_same() {
+    let api_url = airtable();
+    let dsl = proxy::<schema::gen::Schema>(&api_url).route;
+    let derived = proxy::<derived::gen::Schema>(&api_url).route;
+    let requests = vec![
+        ("GET", "/invoice?expand=from,client,items.rate.unit", None),
+        ("GET", "/invoice?was_paid=true&keys=camelCase", None),
+        (
+            "GET",
+            "/invoice/1?fields=number,items.subtotal,items.rate",
+            None,
+        ),
+        (
+            "GET",
+            "/invoice_client/recCLIENT00000001?expand=invoices",
+            None,
+        ),
+        (
+            "POST",
+            "/invoice/records?keys=source",
+            Some(json!(["recINVOICE0000002", "recINVOICE0000001"])),
+        ),
+        (
+            "POST",
+            "/invoice/batch?expand=client",
+            Some(json!([
+                "2020-002",
+                "recINVOICE0000001",
+                "recMISSING0000001"
+            ])),
+        ),
+        ("GET", "/invoice_item/recITEM000000002?expand=rate", None),
+        ("GET", "/invoice?sort=total", None),
+    ];
+    for (method, path, body) in requests {
+        let request = || {
+            let request = warp::test::request().method(method).path(path);
+            match &body {
+                Some(body) => request.json(body),
+                None => request,
+            }
+        };
+        let expected = request().reply(&dsl).await;
+        let actual = request().reply(&derived).await;
+        assert_eq!(actual.status(), expected.status(), "{} {}", method, path);
+        assert_eq!(actual.body(), expected.body(), "{} {}", method, path);
+    }
+}
@@ -0,0 +1,8 @@
+//! The mistakes `#[derive(AirtableTable)]` reports, at the attribute that
+//! has them, see `tests/ui`.
+
+#[test]
+fn derive_errors() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
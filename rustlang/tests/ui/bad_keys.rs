@@ -0,0 +1,11 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice")]
+#[airtable(endpoint = find, arg = String, keys = kebab_case, exec = [into_vec, Invoice::fetch_and_create_first])]
+pub struct Invoice {
+    #[airtable(source = "Invoice Number")]
+    pub number: String,
+}
+
+fn main() {}
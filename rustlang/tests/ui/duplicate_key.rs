@@ -0,0 +1,10 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice")]
+pub struct Invoice {
+    #[airtable(source = "Number", source = "Invoice Number")]
+    pub number: String,
+}
+
+fn main() {}
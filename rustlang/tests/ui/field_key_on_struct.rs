@@ -0,0 +1,10 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice", source = "Invoice")]
+pub struct Invoice {
+    #[airtable(source = "Invoice Number")]
+    pub number: String,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice")]
+pub struct Invoice {
+    #[airtable(filter)]
+    pub number: String,
+}
+
+fn main() {}
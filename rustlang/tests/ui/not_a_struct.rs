@@ -0,0 +1,10 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice")]
+pub enum Invoice {
+    Paid,
+    Unpaid,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Invoice")]
+pub struct Invoice {
+    #[airtable(source = "Invoice Number", required, default = String::new())]
+    pub number: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use airtable_proxy::*;
+
+#[derive(Debug, Serialize, AirtableTable)]
+#[airtable(table = "Clients")]
+pub struct Client {
+    #[airtable(source = "Company")]
+    pub company: String,
+    #[airtable(reverse = Client, source = "Client", exec = id)]
+    pub invoices: Option<Vec<Client>>,
+}
+
+fn main() {}